@@ -0,0 +1,94 @@
+use crate::texture::Texture;
+use std::time::Duration;
+
+// What shows through the cells no mesh was drawn to
+pub struct Background {
+    pub color: (u8, u8, u8),
+    pub image: Option<Texture>, // Scaled to fit the frame, over the color
+    pub adapt: bool, // Keeps the frame readable over the color, see Context::adapt_to_background
+}
+
+impl Default for Background {
+    fn default() -> Background {
+        Background {
+            color: (25, 25, 25),
+            image: None,
+            adapt: false,
+        }
+    }
+}
+
+// Reads an OSC 11 reply like "\x1b]11;rgb:ffff/ffff/ffff\x07", each channel has 1 to 4 hex digits
+fn parse_osc_11(reply: &str) -> Option<(u8, u8, u8)> {
+    let start = reply.find("rgb:")? + 4;
+    let end = reply[start..]
+        .find(&['\x07', '\x1b'][..])
+        .map_or(reply.len(), |end| start + end);
+    let channels: Vec<u8> = reply[start..end]
+        .split('/')
+        .map(|hex| {
+            let value = u32::from_str_radix(hex, 16).ok()?;
+            let max = (1u32 << (4 * hex.len().min(4) as u32)) - 1;
+            Some((value.min(max) * 255 / max) as u8)
+        })
+        .collect::<Option<_>>()?;
+    match channels.as_slice() {
+        [r, g, b] => Some((*r, *g, *b)),
+        _ => None,
+    }
+}
+
+// Asks the terminal for its background color with the OSC 11 query, None if it
+// doesn't answer within the timeout or there's no terminal to ask. This has to
+// run before raw mode is enabled, as it leaves raw mode disabled
+#[cfg(unix)]
+pub fn query_terminal_background(timeout: Duration) -> Option<(u8, u8, u8)> {
+    use std::fs::OpenOptions;
+    use std::io::{Read, Write};
+    use std::os::unix::io::AsRawFd;
+    use std::time::Instant;
+
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/tty")
+        .ok()?;
+    // The reply isn't followed by a newline, so it can only be read without line buffering
+    crossterm::terminal::enable_raw_mode().ok()?;
+    let mut reply = vec![];
+    if tty
+        .write_all(b"\x1b]11;?\x07")
+        .and_then(|_| tty.flush())
+        .is_ok()
+    {
+        let deadline = Instant::now() + timeout;
+        let mut byte = [0u8];
+        while !reply.ends_with(b"\x07") && !reply.ends_with(b"\x1b\\") {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let mut poll = libc::pollfd {
+                fd: tty.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            // Polling first keeps a terminal that never answers from blocking the read forever.
+            // crossterm's own poll isn't used, its event reader would swallow the reply
+            if left == Duration::from_secs(0)
+                // SAFETY: poll points at exactly one pollfd, as the count of 1 says, and it lives
+                // on the stack for the whole call. Its fd is tty's, which owns it and stays open
+                // until the end of this function. libc::poll only writes the pollfd's revents
+                || unsafe { libc::poll(&mut poll, 1, left.as_millis() as libc::c_int) } <= 0
+                || tty.read(&mut byte).unwrap_or(0) == 0
+            {
+                break;
+            }
+            reply.push(byte[0]);
+        }
+    }
+    crossterm::terminal::disable_raw_mode().ok()?;
+    parse_osc_11(&String::from_utf8_lossy(&reply))
+}
+
+#[cfg(not(unix))]
+pub fn query_terminal_background(_timeout: Duration) -> Option<(u8, u8, u8)> {
+    None
+}
@@ -0,0 +1,253 @@
+use crate::geometry::{SimpleMesh, Surface, Triangle, AABB};
+use nalgebra::Vector4;
+use std::error::Error;
+use std::fs;
+
+const MAGIC: &[u8; 8] = b"SLOTHMSH";
+const VERSION: u32 = 4;
+
+// The cache kept next to an input file
+pub fn cache_filename(filename: &str) -> String {
+    format!("{}.slothcache", filename)
+}
+
+fn push_f32s(bytes: &mut Vec<u8>, values: &[f32]) {
+    for value in values {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+fn push_vector(bytes: &mut Vec<u8>, v: &Vector4<f32>) {
+    push_f32s(bytes, &[v.x, v.y, v.z, v.w]);
+}
+
+// Encodes loaded meshes as little endian binary, with the options they were
+// preprocessed with so a cache made with other options is never mistaken for
+// this one
+pub fn encode_cache(meshes: &[SimpleMesh], options: &str) -> Vec<u8> {
+    let mut bytes = vec![];
+    bytes.extend_from_slice(MAGIC);
+    bytes.extend_from_slice(&VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(options.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(options.as_bytes());
+    bytes.extend_from_slice(&(meshes.len() as u32).to_le_bytes());
+    for mesh in meshes {
+        bytes.extend_from_slice(&(mesh.name.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(mesh.name.as_bytes());
+        let emissive = mesh.surface.emissive;
+        bytes.extend_from_slice(&[emissive.0, emissive.1, emissive.2, mesh.surface.unlit as u8]);
+        push_vector(&mut bytes, &mesh.bounding_box.min);
+        push_vector(&mut bytes, &mesh.bounding_box.max);
+        bytes.extend_from_slice(&(mesh.triangles.len() as u32).to_le_bytes());
+        for triangle in &mesh.triangles {
+            bytes.extend_from_slice(&[triangle.color.0, triangle.color.1, triangle.color.2]);
+            push_vector(&mut bytes, &triangle.v1);
+            push_vector(&mut bytes, &triangle.v2);
+            push_vector(&mut bytes, &triangle.v3);
+            let diagonals = triangle
+                .diagonals
+                .iter()
+                .enumerate()
+                .fold(0u8, |bits, (i, &diagonal)| bits | (diagonal as u8) << i);
+            bytes.push(diagonals);
+            match triangle.uvs {
+                None => bytes.push(0),
+                Some(uvs) => {
+                    bytes.push(1);
+                    for uv in &uvs {
+                        push_f32s(&mut bytes, &[uv.0, uv.1]);
+                    }
+                }
+            }
+        }
+        bytes.extend_from_slice(&(mesh.vertices.len() as u32).to_le_bytes());
+        for vertex in &mesh.vertices {
+            push_vector(&mut bytes, vertex);
+        }
+    }
+    bytes
+}
+
+// Reads the cache front to back, any read past its end means it was cut short
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], Box<dyn Error>> {
+        if count > self.bytes.len() {
+            return Err("the cache is truncated".into());
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.take(1)?[0])
+    }
+    fn u32(&mut self) -> Result<u32, Box<dyn Error>> {
+        let mut word = [0; 4];
+        word.copy_from_slice(self.take(4)?);
+        Ok(u32::from_le_bytes(word))
+    }
+    fn f32(&mut self) -> Result<f32, Box<dyn Error>> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+    fn vector(&mut self) -> Result<Vector4<f32>, Box<dyn Error>> {
+        Ok(Vector4::new(
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+            self.f32()?,
+        ))
+    }
+}
+
+// Decodes meshes written by encode_cache, None if they were preprocessed with
+// other options or by another version of the format
+pub fn decode_cache(
+    bytes: &[u8],
+    options: &str,
+) -> Result<Option<Vec<SimpleMesh>>, Box<dyn Error>> {
+    let mut reader = Reader { bytes };
+    if reader.take(MAGIC.len())? != MAGIC {
+        return Err("not a sloth cache".into());
+    }
+    if reader.u32()? != VERSION {
+        return Ok(None);
+    }
+    let length = reader.u32()? as usize;
+    if reader.take(length)? != options.as_bytes() {
+        return Ok(None);
+    }
+    let mut meshes = vec![];
+    for _ in 0..reader.u32()? {
+        let length = reader.u32()? as usize;
+        let name = String::from_utf8(reader.take(length)?.to_vec())?;
+        let surface = Surface {
+            emissive: (reader.u8()?, reader.u8()?, reader.u8()?),
+            unlit: reader.u8()? != 0,
+        };
+        let bounding_box = AABB::new(reader.vector()?, reader.vector()?);
+        let count = reader.u32()? as usize;
+        let mut triangles = Vec::with_capacity(count.min(reader.bytes.len()));
+        for _ in 0..count {
+            let color = (reader.u8()?, reader.u8()?, reader.u8()?);
+            let (v1, v2, v3) = (reader.vector()?, reader.vector()?, reader.vector()?);
+            let bits = reader.u8()?;
+            let uvs = match reader.u8()? {
+                0 => None,
+                _ => Some([
+                    (reader.f32()?, reader.f32()?),
+                    (reader.f32()?, reader.f32()?),
+                    (reader.f32()?, reader.f32()?),
+                ]),
+            };
+            triangles.push(Triangle {
+                color,
+                v1,
+                v2,
+                v3,
+                diagonals: [bits & 1 != 0, bits & 2 != 0, bits & 4 != 0],
+                uvs,
+            });
+        }
+        let count = reader.u32()? as usize;
+        let mut vertices = Vec::with_capacity(count.min(reader.bytes.len()));
+        for _ in 0..count {
+            vertices.push(reader.vector()?);
+        }
+        // The box is kept as it was cached, rather than fit again
+        meshes.push(SimpleMesh {
+            bounding_box,
+            surface,
+            ..SimpleMesh::new(name, triangles, vertices)
+        });
+    }
+    Ok(Some(meshes))
+}
+
+// The cached meshes of an input file, None if there's no cache newer than the file made with these options
+pub fn read_cache(
+    filename: &str,
+    options: &str,
+) -> Result<Option<Vec<SimpleMesh>>, Box<dyn Error>> {
+    let cache = cache_filename(filename);
+    let modified = |path: &str| fs::metadata(path).and_then(|metadata| metadata.modified());
+    match (modified(filename), modified(&cache)) {
+        (Ok(source), Ok(cached)) if cached >= source => {}
+        _ => return Ok(None),
+    }
+    decode_cache(&fs::read(&cache)?, options)
+        .map_err(|e| format!("cache: [{}] couldn't be read, {}", cache, e).into())
+}
+
+pub fn write_cache(
+    filename: &str,
+    meshes: &[SimpleMesh],
+    options: &str,
+) -> Result<(), Box<dyn Error>> {
+    let cache = cache_filename(filename);
+    fs::write(&cache, encode_cache(meshes, options))
+        .map_err(|e| format!("cache: [{}] couldn't be written, {}", cache, e).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn meshes_survive_a_round_trip() {
+        let triangle = Triangle {
+            color: (10, 20, 30),
+            v1: Vector4::new(0.0, 0.0, 0.0, 1.0),
+            v2: Vector4::new(1.0, 0.0, -0.5, 1.0),
+            v3: Vector4::new(0.0, 1.0, 0.25, 1.0),
+            diagonals: [true, false, true],
+            uvs: Some([(0.0, 0.0), (1.0, 0.0), (0.0, 1.0)]),
+        };
+        let untextured = Triangle {
+            uvs: None,
+            diagonals: [false, true, false],
+            ..triangle.clone()
+        };
+        let mut meshes = vec![
+            SimpleMesh::new("volume".to_string(), vec![triangle, untextured], vec![]),
+            SimpleMesh::new(
+                "points".to_string(),
+                vec![],
+                vec![Vector4::new(2.0, 3.0, 4.0, 1.0)],
+            ),
+        ];
+        meshes[0].surface = Surface {
+            emissive: (1, 2, 3),
+            unlit: true,
+        };
+        let bytes = encode_cache(&meshes, "options");
+        let decoded = decode_cache(&bytes, "options").unwrap().unwrap();
+
+        assert_eq!(decoded.len(), meshes.len());
+        for (decoded, mesh) in decoded.iter().zip(&meshes) {
+            assert_eq!(decoded.name, mesh.name);
+            assert_eq!(decoded.bounding_box.min, mesh.bounding_box.min);
+            assert_eq!(decoded.bounding_box.max, mesh.bounding_box.max);
+            assert_eq!(decoded.vertices, mesh.vertices);
+            assert_eq!(decoded.surface.emissive, mesh.surface.emissive);
+            assert_eq!(decoded.surface.unlit, mesh.surface.unlit);
+            assert_eq!(decoded.triangles.len(), mesh.triangles.len());
+            for (a, b) in decoded.triangles.iter().zip(&mesh.triangles) {
+                assert_eq!((a.color, a.v1, a.v2, a.v3), (b.color, b.v1, b.v2, b.v3));
+                assert_eq!((a.diagonals, a.uvs), (b.diagonals, b.uvs));
+            }
+        }
+    }
+
+    #[test]
+    fn other_options_and_truncation_are_caught() {
+        let meshes = vec![SimpleMesh::new("cube".to_string(), vec![], vec![])];
+        let bytes = encode_cache(&meshes, "options");
+        assert!(decode_cache(&bytes, "other options").unwrap().is_none());
+        assert!(decode_cache(&bytes[..bytes.len() - 1], "options").is_err());
+        assert!(decode_cache(b"NOTSLOTH", "options").is_err());
+    }
+}
@@ -0,0 +1,56 @@
+use std::error::Error;
+use std::io::{stdin, BufRead};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+
+// One line read from stdin by --stdin-commands
+pub enum Command {
+    Rotate(f32, f32, f32), // Sets the static rotation like -x, -y and -z do
+    Speed(f32),            // Sets the turntable's speed in rad/s
+    Load(String), // Replaces the scene, filenames are split on spaces like the input filename(s)
+    Screenshot(String), // Writes the last frame as plain text to a file
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, Box<dyn Error>> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let rest: Vec<&str> = words.collect();
+        match (command, rest.len()) {
+            ("rotate", 3) => Ok(Command::Rotate(
+                rest[0].parse()?,
+                rest[1].parse()?,
+                rest[2].parse()?,
+            )),
+            ("speed", 1) => Ok(Command::Speed(rest[0].parse()?)),
+            ("load", count) if count > 0 => Ok(Command::Load(rest.join(" "))),
+            ("screenshot", 1) => Ok(Command::Screenshot(rest[0].to_string())),
+            ("quit", 0) => Ok(Command::Quit),
+            _ => Err(format!(
+                "unknown command [{}], expected rotate <x> <y> <z>, speed <s>, load <filename(s)>, screenshot <filename> or quit",
+                line.trim()
+            )
+            .into()),
+        }
+    }
+}
+
+// Reads stdin on its own thread so the render loop can take lines without ever blocking
+pub fn spawn_stdin_commands() -> Receiver<String> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+    });
+    receiver
+}
@@ -1,21 +1,125 @@
-use crate::geometry::SimpleMesh;
+use crate::background::Background;
+use crate::geometry::fit_extent;
+use crate::rasterizer::{density, luminance, ClipPlane};
+use crate::scene::Scene;
 use crossterm::{
     cursor,
     style::{style, Color, PrintStyledContent},
     terminal, QueueableCommand,
 };
-use nalgebra::Matrix4;
+use nalgebra::{Matrix4, Vector4};
 use std::error::Error;
 use std::f32;
 use std::io::stdout;
 
+// Counts kept across every frame drawn to a context
+#[derive(Default)]
+pub struct RenderStats {
+    pub triangles_drawn: u64,
+    pub triangles_culled: u64, // Facing away from the camera, or without any area
+    pub fragments: u64,        // Fragments that passed the depth test
+}
+
+// Which fragment is kept when a fragment lands at the depth already in the buffer, give or take
+// the rounding of coplanar faces
+#[derive(Clone, Copy, PartialEq)]
+pub enum DepthTiebreak {
+    First, // The one drawn first, meshes and triangles are drawn in the order they were loaded
+    Last,
+    Brightest, // The one with the more luminant color, then the denser character
+    Blend,     // The average color of every fragment at that depth, with the densest character
+}
+
+// A drawn cell's color darkened as much as its character is sparse, None where nothing was drawn
+pub fn shaded_color(pixel: Cell) -> Option<(u8, u8, u8)> {
+    let weight = match (pixel.0, density(pixel.0)) {
+        (' ', _) => return None,
+        (_, Some(density)) => density as f32 / 9.0,
+        (_, None) => 1.0, // Lines and replaced fill characters
+    };
+    let darken = |c: u8| (f32::from(c) * weight).round() as u8;
+    Some((
+        darken((pixel.1).0),
+        darken((pixel.1).1),
+        darken((pixel.1).2),
+    ))
+}
+
+// Mirrors the rows of a buffer laid out like the frame buffer
+fn mirror<T>(buffer: &mut [T], rows: &[std::ops::Range<usize>], x: bool, y: bool) {
+    if x {
+        for row in rows {
+            buffer[row.clone()].reverse();
+        }
+    }
+    if y {
+        for top in 0..rows.len() / 2 {
+            let bottom = rows.len() - 1 - top;
+            for (a, b) in rows[top].clone().zip(rows[bottom].clone()) {
+                buffer.swap(a, b);
+            }
+        }
+    }
+}
+
+// Through HSV, so the saturation and brightness stay the same
+fn rotate_hue(color: (u8, u8, u8), degrees: f32) -> (u8, u8, u8) {
+    let (r, g, b) = (
+        f32::from(color.0) / 255.0,
+        f32::from(color.1) / 255.0,
+        f32::from(color.2) / 255.0,
+    );
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let chroma = max - min;
+    if chroma == 0.0 {
+        return color; // Grays have no hue to turn
+    }
+    let hue = if max == r {
+        (g - b) / chroma
+    } else if max == g {
+        (b - r) / chroma + 2.0
+    } else {
+        (r - g) / chroma + 4.0
+    };
+    let hue = (hue + degrees / 60.0).rem_euclid(6.0);
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let channel = |c: f32| ((c + min) * 255.0).round() as u8;
+    (channel(r), channel(g), channel(b))
+}
+
+// A character and its color, two of them make up a pixel
+pub type Cell = (char, (u8, u8, u8));
+
 pub struct Context {
     pub utransform: Matrix4<f32>,
     pub width: usize,
     pub height: usize,
-    pub frame_buffer: Vec<(char, (u8, u8, u8))>,
+    pub frame_buffer: Vec<Cell>,
+    pub written: Vec<bool>, // Which cells of the frame buffer were drawn to, even with a space
     pub z_buffer: Vec<f32>,
     pub image: bool,
+    pub stats: RenderStats,
+    pub backface_color: Option<(u8, u8, u8)>, // Draws back faces in this color instead of culling them
+    pub clip_plane: Option<ClipPlane>,        // Fragments and points beyond it aren't drawn
+    pub wire_depth_colors: Option<[(u8, u8, u8); 2]>, // Colors lines from near to far instead of by mesh
+    pub depth_range: (f32, f32), // The nearest and farthest depth in the scene, for depth colored lines and splats
+    pub depth_tiebreak: DepthTiebreak,
+    pub normal_buffer: Option<Vec<Vector4<f32>>>, // View-space normals, only kept when they're exported
+    pub solid_buffer: Option<Vec<Cell>>, // The frame before lines were drawn over it, only kept when it's exported
+    pub wire_buffer: Option<Vec<Cell>>,  // Only the lines, only kept when they're exported
+    pub coverage_buffer: Option<Vec<u16>>, // How many fragments share each cell's depth, only kept when blending
+    pub fill_char: Option<char>,           // Replaces the shaded character inside triangles
+    pub edge_char: Option<char>, // Replaces the shaded character within half a pixel of a triangle's edges
+    pub render_scale: f32, // Cells rendered per terminal cell along each axis, above 1.0 they're shown as half blocks
+    pub terminal_size: (usize, usize), // The cells the frame is shown in, the frame's own size unless render_scale scales it
 }
 
 impl Context {
@@ -28,26 +132,334 @@ impl Context {
             width: 0,
             height: 0,
             frame_buffer: vec![],
+            written: vec![],
             z_buffer: vec![],
             image,
+            stats: RenderStats::default(),
+            backface_color: None,
+            clip_plane: None,
+            wire_depth_colors: None,
+            depth_range: (0.0, 0.0),
+            depth_tiebreak: DepthTiebreak::First,
+            normal_buffer: None,
+            solid_buffer: None,
+            wire_buffer: None,
+            coverage_buffer: None,
+            fill_char: None,
+            edge_char: None,
+            render_scale: 1.0,
+            terminal_size: (0, 0),
         }
     }
     pub fn clear(&mut self) {
+        self.clear_frame_buffer();
+        self.z_buffer = vec![f32::MAX; self.width * self.height]; //f32::MAX is written to the z-buffer as an infinite back-wall to render with
+        if let Some(normals) = &mut self.normal_buffer {
+            *normals = vec![Vector4::zeros(); self.width * self.height];
+        }
+        for layer in vec![&mut self.solid_buffer, &mut self.wire_buffer]
+            .into_iter()
+            .flatten()
+        {
+            *layer = vec![(' ', (0, 0, 0)); self.width * self.height];
+        }
+        if let Some(coverage) = &mut self.coverage_buffer {
+            *coverage = vec![0; self.width * self.height];
+        }
+    }
+    // Blanks the characters and colors but keeps the depth drawn so far
+    pub fn clear_frame_buffer(&mut self) {
         self.frame_buffer = vec![
             (' ', (0, 0, 0));
             if self.image {
                 self.width * self.height + self.height
             } else {
                 self.width * self.height
-            } as usize
+            }
         ];
-        self.z_buffer = vec![f32::MAX; self.width * self.height as usize]; //f32::MAX is written to the z-buffer as an infinite back-wall to render with
+        self.written = vec![false; self.frame_buffer.len()];
+        if self.image {
+            for y in 0..self.height {
+                self.frame_buffer[y * self.width + 1] = ('\n', (0, 0, 0)); // Every row ends one cell in, because meshes are never drawn to the first two cells
+            }
+        }
+    }
+    // Draws a pixel, which is two cells wide, at its index in the z-buffer
+    pub fn plot(&mut self, id: usize, pixel: Cell) {
+        self.frame_buffer[id] = pixel;
+        self.frame_buffer[id + 1] = pixel;
+        self.written[id] = true;
+        self.written[id + 1] = true;
+    }
+    // The cells of every row on screen, leaving out the newlines image frames end their rows with
+    fn rows(&self) -> Vec<std::ops::Range<usize>> {
+        (0..self.height)
+            .map(|y| {
+                if self.image {
+                    y * self.width + 2..(y + 1) * self.width + 1
+                } else {
+                    y * self.width..(y + 1) * self.width
+                }
+            })
+            .collect()
+    }
+    // Mirrors the finished frame horizontally and/or vertically
+    pub fn flip(&mut self, x: bool, y: bool) {
+        let rows = self.rows();
+        mirror(&mut self.frame_buffer, &rows, x, y);
+        mirror(&mut self.written, &rows, x, y);
+        if x != y {
+            // Diagonal lines have to lean the other way once mirrored along one axis
+            for pixel in &mut self.frame_buffer {
+                pixel.0 = match pixel.0 {
+                    '/' => '\\',
+                    '\\' => '/',
+                    other => other,
+                };
+            }
+        }
+    }
+    // One value per pixel from a buffer laid out like the z-buffer, row by row and mirrored like flip
+    fn pixel_rows<T: Copy>(&self, buffer: &[T], x: bool, y: bool) -> Vec<Vec<T>> {
+        let mut rows: Vec<Vec<T>> = (0..self.height)
+            .map(|row| {
+                (0..self.width / 2)
+                    .map(|column| buffer[row * self.width + column * 2])
+                    .collect()
+            })
+            .collect();
+        if x {
+            for row in &mut rows {
+                row.reverse();
+            }
+        }
+        if y {
+            rows.reverse();
+        }
+        rows
+    }
+    pub fn depth_rows(&self, x: bool, y: bool) -> Vec<Vec<f32>> {
+        self.pixel_rows(&self.z_buffer, x, y)
+    }
+    // The view-space normals (x right, y up, z towards the camera), zero where nothing was drawn
+    pub fn normal_rows(&self, x: bool, y: bool) -> Vec<Vec<Vector4<f32>>> {
+        let mut rows = match &self.normal_buffer {
+            None => return vec![],
+            Some(normals) => self.pixel_rows(normals, x, y),
+        };
+        for normal in rows.iter_mut().flatten() {
+            // Mirroring the image mirrors the surfaces in it too
+            if x {
+                normal.x = -normal.x;
+            }
+            if y {
+                normal.y = -normal.y;
+            }
+        }
+        rows
+    }
+    // The shaded colors of an exported layer, mirrored like depth_rows, None where nothing was drawn
+    pub fn layer_rows(
+        &self,
+        layer: &Option<Vec<Cell>>,
+        x: bool,
+        y: bool,
+    ) -> Vec<Vec<Option<(u8, u8, u8)>>> {
+        match layer {
+            None => vec![],
+            Some(layer) => self
+                .pixel_rows(layer, x, y)
+                .into_iter()
+                .map(|row| row.into_iter().map(shaded_color).collect())
+                .collect(),
+        }
+    }
+    // The colors of the finished frame as it's shown, over the background where nothing was drawn
+    pub fn color_rows(&self, background: &Background) -> Vec<Vec<Option<(u8, u8, u8)>>> {
+        let colors: Vec<Option<(u8, u8, u8)>> = (0..self.frame_buffer.len())
+            .map(|index| {
+                Some(
+                    shaded_color(self.frame_buffer[index])
+                        .unwrap_or_else(|| self.background_at(background, index)),
+                )
+            })
+            .collect();
+        self.pixel_rows(&colors, false, false) // The frame was mirrored when it was drawn
+    }
+    // The frame's characters as lines of text
+    pub fn to_text(&self) -> String {
+        if self.image {
+            self.frame_buffer.iter().map(|pixel| pixel.0).collect() // Rows already end in newlines
+        } else {
+            self.frame_buffer
+                .chunks(self.width.max(1))
+                .map(|row| {
+                    row.iter()
+                        .map(|pixel| pixel.0)
+                        .chain(Some('\n'))
+                        .collect::<String>()
+                })
+                .collect()
+        }
+    }
+    // The cell's background, either its color or its image scaled to fit the frame
+    fn background_at(&self, background: &Background, index: usize) -> (u8, u8, u8) {
+        match &background.image {
+            // A shaded space is drawn too, and the row endings keep the default
+            Some(image) if !self.written[index] && self.frame_buffer[index].0 == ' ' => {
+                let cell = index.saturating_sub(2); // Undo the offset of the row endings
+                image.sample(
+                    (cell % self.width) as f32 / self.width as f32,
+                    (cell / self.width) as f32 / self.height as f32,
+                )
+            }
+            _ => background.color,
+        }
+    }
+    // Turns the hue of every drawn cell's color around the color wheel by degrees
+    pub fn rotate_hues(&mut self, degrees: f32) {
+        for pixel in &mut self.frame_buffer {
+            pixel.1 = rotate_hue(pixel.1, degrees);
+        }
+    }
+    // Keeps the frame readable over the background color, on a light one the shading ramp is
+    // reversed (dense characters read as dark) and colors too close to it are pushed away
+    pub fn adapt_to_background(&mut self, background: (u8, u8, u8)) {
+        const RAMP: &str = ".:-=+*#%@";
+        let background = luminance(background);
+        let light = background > 0.5;
+        for pixel in &mut self.frame_buffer {
+            if light {
+                if let Some(index) = RAMP.find(pixel.0) {
+                    pixel.0 = RAMP.chars().rev().nth(index).unwrap();
+                }
+            }
+            if (luminance(pixel.1) - background).abs() < 0.3 {
+                let target = if light { 0.0 } else { 255.0 };
+                let push = |c: u8| ((f32::from(c) + target) * 0.5) as u8;
+                pixel.1 = (push((pixel.1).0), push((pixel.1).1), push((pixel.1).2));
+            }
+        }
+    }
+    // The frame cells behind one of count parts of the terminal along an axis of this many cells
+    fn block(index: usize, count: usize, cells: usize) -> std::ops::Range<usize> {
+        let start = index * cells / count;
+        start..((index + 1) * cells / count).max(start + 1).min(cells)
+    }
+    // The average shaded color of a block of the frame, with the background where nothing was drawn
+    fn block_color(
+        &self,
+        background: &Background,
+        columns: std::ops::Range<usize>,
+        rows: std::ops::Range<usize>,
+    ) -> (u8, u8, u8) {
+        let mut sum = (0.0, 0.0, 0.0);
+        let mut count = 0;
+        for y in rows {
+            for x in columns.clone() {
+                let index = y * self.width + x;
+                let color = shaded_color(self.frame_buffer[index])
+                    .unwrap_or_else(|| self.background_at(background, index));
+                sum.0 += f32::from(color.0);
+                sum.1 += f32::from(color.1);
+                sum.2 += f32::from(color.2);
+                count += 1;
+            }
+        }
+        let average = |c: f32| (c / count.max(1) as f32) as u8;
+        (average(sum.0), average(sum.1), average(sum.2))
+    }
+    // Maps a frame rendered at render_scale down to the terminal, every cell a half block colored
+    // with the top half of its block in front and the bottom half behind
+    fn flush_half_blocks(
+        &self,
+        color: bool,
+        background: &Background,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut stdout = stdout();
+        let (columns, rows) = self.terminal_size;
+        for row in 0..rows {
+            for column in 0..columns {
+                let across = Context::block(column, columns, self.width);
+                if !color {
+                    // Without colors the densest character of the block stands in for it
+                    let character = Context::block(row, rows, self.height)
+                        .flat_map(|y| across.clone().map(move |x| y * self.width + x))
+                        .map(|index| self.frame_buffer[index].0)
+                        .max_by_key(|&character| density(character))
+                        .unwrap_or(' ');
+                    stdout.queue(PrintStyledContent(style(character)))?;
+                    continue;
+                }
+                let top = self.block_color(
+                    background,
+                    across.clone(),
+                    Context::block(row * 2, rows * 2, self.height),
+                );
+                let bottom = self.block_color(
+                    background,
+                    across,
+                    Context::block(row * 2 + 1, rows * 2, self.height),
+                );
+                let styled = style('\u{2580}')
+                    .with(Color::Rgb {
+                        r: top.0,
+                        g: top.1,
+                        b: top.2,
+                    })
+                    .on(Color::Rgb {
+                        r: bottom.0,
+                        g: bottom.1,
+                        b: bottom.2,
+                    });
+                stdout.queue(PrintStyledContent(styled))?;
+            }
+        }
+        Ok(())
+    }
+    // Maps the frame down to lines of braille characters, every one 2 by 4 dots each standing in
+    // for a block of pixels, lit when the densest character of the block is dense enough to
+    // outweigh the dot's place in an ordered dither
+    pub fn braille_text(&self, columns: usize, rows: usize) -> String {
+        const THRESHOLDS: [[usize; 2]; 4] = [[0, 8], [12, 4], [3, 11], [15, 7]]; // Out of 16
+        const BITS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+        let densest = " .:-=+*#%@".len() - 1;
+        let mut text = String::new();
+        for row in 0..rows {
+            for column in 0..columns {
+                let mut dots = 0;
+                for (dy, (thresholds, bits)) in THRESHOLDS.iter().zip(&BITS).enumerate() {
+                    let down = Context::block(row * 4 + dy, rows * 4, self.height);
+                    for dx in 0..2 {
+                        let across = Context::block(column * 2 + dx, columns * 2, self.width / 2);
+                        let shade = down
+                            .clone()
+                            .flat_map(|y| across.clone().map(move |x| y * self.width + x * 2))
+                            .filter_map(|index| density(self.frame_buffer[index].0))
+                            .max()
+                            .unwrap_or(0);
+                        if shade * 32 > (thresholds[dx] * 2 + 1) * densest {
+                            dots |= bits[dx];
+                        }
+                    }
+                }
+                text.push(std::char::from_u32(0x2800 + dots).unwrap());
+            }
+            text.push('\n');
+        }
+        text
     }
     pub fn camera(&mut self, proj: Matrix4<f32>, view: Matrix4<f32>) -> &Matrix4<f32> {
         self.utransform = proj * view;
         &self.utransform
     }
-    pub fn flush(&self, color: bool, webify: bool) -> Result<(), Box<dyn Error>> {
+    // Prints the frame over the scene's background
+    pub fn flush(
+        &self,
+        color: bool,
+        webify: bool,
+        background: &Background,
+    ) -> Result<(), Box<dyn Error>> {
         let mut stdout = stdout();
 
         if !self.image {
@@ -55,12 +467,16 @@ impl Context {
         }
 
         match (color, webify) {
+            (_, false) if !self.image && self.render_scale > 1.0 => {
+                self.flush_half_blocks(color, background)?
+            }
             (false, _) => {
                 let frame: String = self.frame_buffer.iter().map(|pixel| pixel.0).collect();
                 println!("{}", frame);
             }
             (true, false) => {
-                for pixel in &self.frame_buffer {
+                for (index, pixel) in self.frame_buffer.iter().enumerate() {
+                    let background = self.background_at(background, index);
                     let styled = style(pixel.0)
                         .with(Color::Rgb {
                             r: (pixel.1).0,
@@ -68,20 +484,30 @@ impl Context {
                             b: (pixel.1).2,
                         })
                         .on(Color::Rgb {
-                            r: 25,
-                            g: 25,
-                            b: 25,
+                            r: background.0,
+                            g: background.1,
+                            b: background.2,
                         });
                     stdout.queue(PrintStyledContent(styled))?;
                 }
             }
             (true, true) => {
-                for pixel in &self.frame_buffer {
+                for (index, pixel) in self.frame_buffer.iter().enumerate() {
+                    let background = if background.image.is_some() {
+                        let background = self.background_at(background, index);
+                        format!(
+                            ";background-color:rgb({},{},{})",
+                            background.0, background.1, background.2
+                        )
+                    } else {
+                        String::new()
+                    };
                     print!(
-                        "<span style=\"color:rgb({},{},{})\">{}",
+                        "<span style=\"color:rgb({},{},{}){}\">{}",
                         (pixel.1).0,
                         (pixel.1).1,
                         (pixel.1).2,
+                        background,
                         pixel.0
                     );
                 }
@@ -90,10 +516,11 @@ impl Context {
 
         Ok(())
     }
+    // Fits the scene's meshes to the frame, zoomed by its camera
     pub fn update(
         &mut self,
         mut old_size: (u16, u16),
-        meshes: &[SimpleMesh],
+        scene: &Scene,
     ) -> Result<(), Box<dyn Error>> {
         let terminal_size = if self.image {
             (self.width as u16, self.height as u16)
@@ -103,24 +530,26 @@ impl Context {
 
         if old_size != terminal_size {
             old_size = terminal_size; // It changed! Set new size
-            let mut scale: f32 = 0.0; // The scene's scale
-            for mesh in meshes {
-                // This calculates the maximum axis value (x y or z) in all meshes
-                scale = scale
-                    .max(mesh.bounding_box.max.x)
-                    .max(mesh.bounding_box.max.y)
-                    .max(mesh.bounding_box.max.z);
-            }
-            scale = f32::from(old_size.1).min(f32::from(old_size.0) / 2.0) / scale / 2.0; // Constrain to width and height, whichever is smaller
+            self.terminal_size = (old_size.0 as usize, old_size.1 as usize);
+            let grid = if self.image {
+                (f32::from(old_size.0), f32::from(old_size.1))
+            } else {
+                (
+                    (f32::from(old_size.0) * self.render_scale).floor(),
+                    (f32::from(old_size.1) * self.render_scale).floor(),
+                )
+            };
+            let mut scale = fit_extent(&scene.meshes); // The furthest any mesh reaches, all of them together
+            scale = grid.1.min(grid.0 / 2.0) / scale / 2.0 * scene.camera.zoom; // Constrain to width and height, whichever is smaller
             let t = Matrix4::new(
                 scale,
                 0.0,
                 0.0,
-                f32::from(old_size.0) / 4.0, // X translation is divided by 4 because there's a 1 char space between charxels
+                grid.0 / 4.0, // X translation is divided by 4 because there's a 1 char space between charxels
                 0.0,
                 -scale,
                 0.0,
-                f32::from(old_size.1) / 2.0, // Y translation is divided by 2 to center
+                grid.1 / 2.0, // Y translation is divided by 2 to center
                 0.0,
                 0.0,
                 scale,
@@ -132,11 +561,41 @@ impl Context {
             );
             self.utransform = t;
             if !self.image {
-                self.width = old_size.0 as usize;
-                self.height = (old_size.1) as usize;
+                self.width = grid.0 as usize;
+                self.height = grid.1 as usize;
             }
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn braille_dots_follow_the_shading() {
+        let mut context = Context::blank(false);
+        context.width = 8;
+        context.height = 8;
+        context.clear();
+        // The left half is drawn as densely as it gets, the right half not at all
+        for y in 0..8 {
+            for x in 0..2 {
+                context.plot(y * 8 + x * 2, ('@', (255, 255, 255)));
+            }
+        }
+        assert_eq!(
+            context.braille_text(2, 2),
+            "\u{28FF}\u{2800}\n\u{28FF}\u{2800}\n"
+        );
+        // The faintest shade lights only the first dot of the dither
+        for y in 0..8 {
+            for x in 0..2 {
+                context.plot(y * 8 + x * 2, ('.', (255, 255, 255)));
+            }
+        }
+        assert_eq!(context.braille_text(1, 1), "\u{2801}\n");
+    }
+}
@@ -0,0 +1,355 @@
+use crate::geometry::{bounding_box, SimpleMesh};
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+// The sum of the squared distances to a set of planes, so how far a merged vertex strays from
+// the faces it replaces can be measured (Garland and Heckbert's quadric error metric)
+type Quadric = Matrix4<f64>;
+
+fn plane_quadric(normal: &Vector3<f64>, point: &Vector3<f64>) -> Quadric {
+    let plane = Vector4::new(normal.x, normal.y, normal.z, -normal.dot(point));
+    plane * plane.transpose()
+}
+
+fn quadric_error(quadric: &Quadric, position: &Vector3<f64>) -> f64 {
+    let v = Vector4::new(position.x, position.y, position.z, 1.0);
+    v.dot(&(quadric * v)).max(0.0)
+}
+
+// Where the two ends of an edge are best merged, and the squared error of merging them there
+fn best_merge(quadric: &Quadric, a: &Vector3<f64>, b: &Vector3<f64>) -> (Vector3<f64>, f64) {
+    let mut candidates = vec![*a, *b, (a + b) * 0.5];
+    let q = quadric;
+    let system = Matrix3::new(
+        q[(0, 0)],
+        q[(0, 1)],
+        q[(0, 2)],
+        q[(1, 0)],
+        q[(1, 1)],
+        q[(1, 2)],
+        q[(2, 0)],
+        q[(2, 1)],
+        q[(2, 2)],
+    );
+    if let Some(inverse) = system.try_inverse() {
+        let optimal = -(inverse * Vector3::new(q[(0, 3)], q[(1, 3)], q[(2, 3)]));
+        // Nearly flat neighbourhoods can put the optimum far off, the edge's own points are safer there
+        if optimal.iter().all(|x| x.is_finite())
+            && (optimal - (a + b) * 0.5).norm() <= (a - b).norm()
+        {
+            candidates.push(optimal);
+        }
+    }
+    candidates
+        .into_iter()
+        .map(|position| (position, quadric_error(quadric, &position)))
+        .min_by(|x, y| x.1.partial_cmp(&y.1).unwrap_or(Ordering::Equal))
+        .unwrap()
+}
+
+fn face_normal(positions: &[Vector3<f64>], face: &[usize; 3]) -> Vector3<f64> {
+    let (a, b, c) = (positions[face[0]], positions[face[1]], positions[face[2]]);
+    (b - a).cross(&(c - a))
+}
+
+// An edge waiting to be collapsed, cheapest first
+struct Collapse {
+    error: f64,
+    ends: (usize, usize),
+    versions: (u32, u32), // The ends' versions when it was queued, it's stale once either changed
+    position: Vector3<f64>,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Collapse) -> bool {
+        self.error == other.error
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Collapse) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    fn cmp(&self, other: &Collapse) -> Ordering {
+        // Reversed, the heap pops the smallest error
+        other
+            .error
+            .partial_cmp(&self.error)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+// A mesh welded into shared vertices, so edges can be collapsed
+struct Collapser {
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    versions: Vec<u32>, // Bumped whenever a vertex moves or goes
+    alive: Vec<bool>,
+    faces: Vec<[usize; 3]>,
+    removed: Vec<bool>,
+    vertex_faces: Vec<Vec<usize>>,
+    queue: BinaryHeap<Collapse>,
+}
+
+impl Collapser {
+    fn new(mesh: &SimpleMesh) -> Collapser {
+        let key = |v: &Vector4<f32>| (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+        let mut shared: HashMap<_, usize> = HashMap::new();
+        let mut positions = vec![];
+        let mut faces = vec![];
+        for triangle in &mesh.triangles {
+            let mut face = [0; 3];
+            for (corner, v) in face
+                .iter_mut()
+                .zip(&[triangle.v1, triangle.v2, triangle.v3])
+            {
+                *corner = *shared.entry(key(v)).or_insert_with(|| {
+                    positions.push(Vector3::new(f64::from(v.x), f64::from(v.y), f64::from(v.z)));
+                    positions.len() - 1
+                });
+            }
+            faces.push(face);
+        }
+        let mut quadrics = vec![Quadric::zeros(); positions.len()];
+        let mut vertex_faces = vec![vec![]; positions.len()];
+        let mut edge_faces: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+        for (index, face) in faces.iter().enumerate() {
+            if let Some(normal) = face_normal(&positions, face).try_normalize(f64::EPSILON) {
+                let quadric = plane_quadric(&normal, &positions[face[0]]);
+                for &corner in face {
+                    quadrics[corner] += quadric;
+                }
+            }
+            for i in 0..3 {
+                vertex_faces[face[i]].push(index);
+                let (a, b) = (face[i], face[(i + 1) % 3]);
+                edge_faces
+                    .entry((a.min(b), a.max(b)))
+                    .or_default()
+                    .push(index);
+            }
+        }
+        // Open boundaries are held in place by a plane standing up along them
+        for (&(a, b), around) in &edge_faces {
+            if let [face] = around.as_slice() {
+                let normal = face_normal(&positions, &faces[*face]);
+                let side = (positions[b] - positions[a]).cross(&normal);
+                if let Some(side) = side.try_normalize(f64::EPSILON) {
+                    let quadric = plane_quadric(&side, &positions[a]);
+                    quadrics[a] += quadric;
+                    quadrics[b] += quadric;
+                }
+            }
+        }
+        let count = positions.len();
+        let mut collapser = Collapser {
+            positions,
+            quadrics,
+            versions: vec![0; count],
+            alive: vec![true; count],
+            removed: vec![false; faces.len()],
+            faces,
+            vertex_faces,
+            queue: BinaryHeap::new(),
+        };
+        for &(a, b) in edge_faces.keys() {
+            if a != b {
+                collapser.queue_edge(a, b);
+            }
+        }
+        collapser
+    }
+    fn queue_edge(&mut self, a: usize, b: usize) {
+        let (position, error) = best_merge(
+            &(self.quadrics[a] + self.quadrics[b]),
+            &self.positions[a],
+            &self.positions[b],
+        );
+        self.queue.push(Collapse {
+            error,
+            ends: (a, b),
+            versions: (self.versions[a], self.versions[b]),
+            position,
+        });
+    }
+    fn neighbours(&self, vertex: usize) -> HashSet<usize> {
+        self.vertex_faces[vertex]
+            .iter()
+            .filter(|&&face| !self.removed[face])
+            .flat_map(|&face| self.faces[face].iter().copied())
+            .filter(|&other| other != vertex)
+            .collect()
+    }
+    // Whether merging a and b at position keeps the surface a manifold without folding faces over
+    fn can_collapse(&self, a: usize, b: usize, position: &Vector3<f64>) -> bool {
+        let shared_faces = self.vertex_faces[a]
+            .iter()
+            .filter(|&&face| !self.removed[face] && self.faces[face].contains(&b))
+            .count();
+        let shared_neighbours = self.neighbours(a).intersection(&self.neighbours(b)).count();
+        if shared_neighbours != shared_faces {
+            return false; // Merging would pinch the surface together
+        }
+        for &vertex in &[a, b] {
+            for &face in &self.vertex_faces[vertex] {
+                let corners = self.faces[face];
+                if self.removed[face] || (corners.contains(&a) && corners.contains(&b)) {
+                    continue;
+                }
+                let corner = |i: usize| match corners[i] {
+                    moved if moved == vertex => *position,
+                    other => self.positions[other],
+                };
+                let after = (corner(1) - corner(0)).cross(&(corner(2) - corner(0)));
+                if face_normal(&self.positions, &corners).dot(&after) <= 0.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    // Collapses the cheapest edges while their squared error stays within the limit
+    fn run(&mut self, limit: f64) {
+        while let Some(collapse) = self.queue.pop() {
+            if collapse.error > limit {
+                break;
+            }
+            let (a, b) = collapse.ends;
+            if !self.alive[a]
+                || !self.alive[b]
+                || collapse.versions != (self.versions[a], self.versions[b])
+                || !self.can_collapse(a, b, &collapse.position)
+            {
+                continue;
+            }
+            self.positions[a] = collapse.position;
+            self.quadrics[a] = self.quadrics[a] + self.quadrics[b];
+            self.alive[b] = false;
+            self.versions[a] += 1;
+            self.versions[b] += 1;
+            for face in std::mem::take(&mut self.vertex_faces[b]) {
+                if self.removed[face] {
+                    continue;
+                }
+                if self.faces[face].contains(&a) {
+                    self.removed[face] = true;
+                } else {
+                    for corner in &mut self.faces[face] {
+                        if *corner == b {
+                            *corner = a;
+                        }
+                    }
+                    self.vertex_faces[a].push(face);
+                }
+            }
+            let removed = &self.removed;
+            self.vertex_faces[a].retain(|&face| !removed[face]);
+            for other in self.neighbours(a) {
+                self.queue_edge(a, other);
+            }
+        }
+    }
+}
+
+// Collapses edges of every mesh until going further would move the surface more than error
+// times the diagonal of the meshes' bounding box, the triangles left keep their colors and UVs
+pub fn decimate(meshes: &mut [SimpleMesh], error: f32) {
+    let bounds = bounding_box(meshes);
+    let diagonal = f64::from((bounds.max - bounds.min).xyz().norm());
+    if !(diagonal > 0.0 && diagonal.is_finite()) {
+        return;
+    }
+    let limit = (f64::from(error) * diagonal).powi(2);
+    for mesh in meshes {
+        let mut collapser = Collapser::new(mesh);
+        collapser.run(limit);
+        let position = |vertex: usize| {
+            let p = collapser.positions[vertex];
+            Vector4::new(p.x as f32, p.y as f32, p.z as f32, 1.0)
+        };
+        let mut triangles = vec![];
+        for (triangle, (face, &removed)) in mesh
+            .triangles
+            .iter()
+            .zip(collapser.faces.iter().zip(&collapser.removed))
+        {
+            if !removed {
+                let mut triangle = triangle.clone();
+                triangle.v1 = position(face[0]);
+                triangle.v2 = position(face[1]);
+                triangle.v3 = position(face[2]);
+                triangles.push(triangle);
+            }
+        }
+        mesh.triangles = triangles;
+        mesh.fit_bounding_box();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Triangle;
+
+    // A flat square of n by n cells, two triangles each
+    fn grid(n: usize) -> SimpleMesh {
+        let point = |x: usize, y: usize| Vector4::new(x as f32, y as f32, 0.0, 1.0);
+        let mut triangles = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                let corners = [
+                    point(x, y),
+                    point(x + 1, y),
+                    point(x + 1, y + 1),
+                    point(x, y + 1),
+                ];
+                for &(i, j, k) in &[(0, 1, 2), (0, 2, 3)] {
+                    triangles.push(Triangle {
+                        color: (255, 255, 255),
+                        v1: corners[i],
+                        v2: corners[j],
+                        v3: corners[k],
+                        diagonals: [false; 3],
+                        uvs: None,
+                    });
+                }
+            }
+        }
+        SimpleMesh::new(String::new(), triangles, vec![])
+    }
+
+    #[test]
+    fn flat_surfaces_collapse_to_a_few_triangles() {
+        let mut meshes = vec![grid(8)];
+        decimate(&mut meshes, 0.001);
+        let triangles = &meshes[0].triangles;
+        assert!(triangles.len() < 8 * 8 * 2 / 4);
+        // Still covers the same square, flat and facing the same way
+        let area: f32 = triangles
+            .iter()
+            .map(|t| (t.v2 - t.v1).xyz().cross(&(t.v3 - t.v1).xyz()).z * 0.5)
+            .sum();
+        assert!((area - 64.0).abs() < 1.0e-3);
+        assert!(triangles
+            .iter()
+            .all(|t| t.v1.z == 0.0 && t.v2.z == 0.0 && t.v3.z == 0.0));
+    }
+
+    #[test]
+    fn no_error_leaves_curved_surfaces_alone() {
+        let mut meshes = vec![grid(4)];
+        for triangle in &mut meshes[0].triangles {
+            for v in &mut [&mut triangle.v1, &mut triangle.v2, &mut triangle.v3] {
+                v.z = (v.x * v.x + v.y * v.y) * 0.1; // A bowl, no vertex lies on its neighbours' planes
+            }
+        }
+        decimate(&mut meshes, 0.0);
+        assert_eq!(meshes[0].triangles.len(), 4 * 4 * 2);
+    }
+}
@@ -1,5 +1,8 @@
-use nalgebra::{Matrix4, Unit, Vector4};
+use crate::rasterizer::{luminance, Shader};
+use nalgebra::{Matrix3, Matrix4, Rotation3, Unit, Vector3, Vector4};
 use std::clone::Clone;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use tobj::{Material, Mesh};
 
 // 2 3D points = Axis aligned bounding box
@@ -21,6 +24,8 @@ pub struct Triangle {
     pub v1: Vector4<f32>,
     pub v2: Vector4<f32>,
     pub v3: Vector4<f32>,
+    pub diagonals: [bool; 3], // Which edges (v1-v2, v2-v3, v3-v1) were added splitting a polygon into triangles
+    pub uvs: Option<[(f32, f32); 3]>, // The texture coordinates at v1, v2 and v3, when the file has them
 }
 
 // Functions for Triangle Struct
@@ -56,6 +61,67 @@ impl Clone for Triangle {
             v1: self.v1,
             v2: self.v2,
             v3: self.v3,
+            diagonals: self.diagonals,
+            uvs: self.uvs,
+        }
+    }
+}
+
+// An edge shared by up to two triangles, found by welding identical vertex positions
+pub struct Edge {
+    pub v1: Vector4<f32>,
+    pub v2: Vector4<f32>,
+    pub faces: (usize, Option<usize>), // Indices into the mesh's triangles, one face = open boundary
+    pub dihedral: f32,                 // Angle between the faces' normals (in radians)
+    pub diagonal: bool,                // Added when a polygon was split, rather than authored
+}
+
+// A vertex drawn on its own as a dot, with the normal of the faces around it
+pub struct Point {
+    pub position: Vector4<f32>,
+    pub normal: Vector4<f32>,
+    pub color: (u8, u8, u8), // The color of the first face using it
+}
+
+// Turns a mesh around an axis through its origin, on its own while the turntable turns the scene
+pub struct Spin {
+    pub axis: Unit<Vector3<f32>>,
+    pub speed: f32, // In radians per second
+    pub angle: f32, // How far it has turned so far
+}
+
+impl Spin {
+    pub fn rotation(&self) -> Matrix4<f32> {
+        Rotation3::from_axis_angle(&self.axis, self.angle).to_homogeneous()
+    }
+}
+
+// How a mesh's faces take light, from the material in its file
+#[derive(Clone, Copy, Default)]
+pub struct Surface {
+    pub emissive: (u8, u8, u8), // The light the faces give off themselves, however they're lit
+    pub unlit: bool,            // Shown at their full color, without shading
+}
+
+impl Surface {
+    // How lit a face is, given how directly it looks at the lights
+    pub fn shade(&self, lit: f32) -> f32 {
+        if self.unlit {
+            1.0
+        } else {
+            lit + luminance(self.emissive)
+        }
+    }
+    // The color a face of this color shows
+    pub fn color(&self, color: (u8, u8, u8)) -> (u8, u8, u8) {
+        if self.unlit {
+            color
+        } else {
+            (
+                color.0.saturating_add(self.emissive.0),
+                color.1.saturating_add(self.emissive.1),
+                color.2.saturating_add(self.emissive.2),
+            )
         }
     }
 }
@@ -69,37 +135,350 @@ pub trait ToSimpleMeshWithMaterial {
 }
 
 pub struct SimpleMesh {
+    pub name: String, // The input filename this mesh was loaded from
+    pub input: usize, // Which of the input files, in the order they were given, this mesh is from
     pub bounding_box: AABB,
     pub triangles: Vec<Triangle>,
+    pub shader: Option<Box<dyn Shader>>, // Overrides the scene's shader for this mesh
+    pub surface: Surface,
+    pub edges: Vec<Edge>,            // Empty until build_edges is called
+    pub points: Vec<Point>,          // Empty until build_points is called
+    pub vertices: Vec<Vector4<f32>>, // The positions of a mesh without faces, like a point cloud
+    pub placement: Matrix4<f32>,     // Positions the mesh after it has been rotated
+    pub wireframe: bool,             // Drawn as its edges over the filled meshes
+    pub spin: Option<Spin>,
+}
+
+impl SimpleMesh {
+    // A mesh loaded from a file, nothing but its geometry set yet and its bounding box fit around it
+    pub fn new(name: String, triangles: Vec<Triangle>, vertices: Vec<Vector4<f32>>) -> SimpleMesh {
+        let mut mesh = SimpleMesh {
+            name,
+            input: 0,
+            bounding_box: AABB::new(Vector4::zeros(), Vector4::zeros()),
+            triangles,
+            shader: None,
+            surface: Surface::default(),
+            edges: vec![],
+            points: vec![],
+            vertices,
+            placement: Matrix4::identity(),
+            wireframe: false,
+            spin: None,
+        };
+        mesh.fit_bounding_box();
+        mesh
+    }
+    // Fits the bounding box around the triangles and the loose vertices
+    pub fn fit_bounding_box(&mut self) {
+        let mut bounding_box = AABB::new(
+            Vector4::new(f32::MAX, f32::MAX, f32::MAX, 1.0),
+            Vector4::new(f32::MIN, f32::MIN, f32::MIN, 1.0),
+        );
+        for triangle in &self.triangles {
+            let aabb = triangle.to_aabb();
+            bounding_box.min = bounding_box.min.inf(&aabb.min);
+            bounding_box.max = bounding_box.max.sup(&aabb.max);
+        }
+        for vertex in &self.vertices {
+            bounding_box.min = bounding_box.min.inf(vertex);
+            bounding_box.max = bounding_box.max.sup(vertex);
+        }
+        self.bounding_box = bounding_box;
+    }
+    // This mutates all triangles into a given Matrix space, and fits the bounding box around them again
+    pub fn mul(&mut self, transform: Matrix4<f32>) -> &mut SimpleMesh {
+        for triangle in &mut self.triangles {
+            triangle.mul(transform);
+        }
+        for vertex in &mut self.vertices {
+            *vertex = transform * *vertex;
+        }
+        self.fit_bounding_box();
+        if !self.edges.is_empty() {
+            self.build_edges();
+        }
+        if !self.points.is_empty() {
+            self.build_points();
+        }
+        self
+    }
+    // Fills the edge list, edges with more than two faces only keep the first two
+    pub fn build_edges(&mut self) {
+        let key = |v: &Vector4<f32>| (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+        let mut shared: HashMap<_, usize> = HashMap::new();
+        let mut edges: Vec<Edge> = vec![];
+        for (face, tri) in self.triangles.iter().enumerate() {
+            let sides = [(tri.v1, tri.v2), (tri.v2, tri.v3), (tri.v3, tri.v1)];
+            for ((v1, v2), &diagonal) in sides.iter().zip(tri.diagonals.iter()) {
+                let (k1, k2) = (key(v1), key(v2));
+                let edge_key = if k1 < k2 { (k1, k2) } else { (k2, k1) };
+                match shared.get(&edge_key) {
+                    Some(&index) if edges[index].faces.1.is_none() => {
+                        let other = &self.triangles[edges[index].faces.0];
+                        edges[index].faces.1 = Some(face);
+                        edges[index].dihedral =
+                            other.normal().dot(&tri.normal()).clamp(-1.0, 1.0).acos();
+                    }
+                    Some(_) => {}
+                    None => {
+                        shared.insert(edge_key, edges.len());
+                        edges.push(Edge {
+                            v1: *v1,
+                            v2: *v2,
+                            faces: (face, None),
+                            dihedral: 0.0,
+                            diagonal,
+                        });
+                    }
+                }
+            }
+        }
+        self.edges = edges;
+    }
+    // Fills the point list with every distinct vertex position, of the faces and the loose vertices
+    pub fn build_points(&mut self) {
+        let key = |v: &Vector4<f32>| (v.x.to_bits(), v.y.to_bits(), v.z.to_bits());
+        let mut shared: HashMap<_, usize> = HashMap::new();
+        let mut points: Vec<Point> = vec![];
+        for tri in &self.triangles {
+            let normal = tri.normal();
+            for v in &[tri.v1, tri.v2, tri.v3] {
+                let index = *shared.entry(key(v)).or_insert_with(|| {
+                    points.push(Point {
+                        position: *v,
+                        normal: Vector4::zeros(),
+                        color: tri.color,
+                    });
+                    points.len() - 1
+                });
+                if normal.iter().all(|n| n.is_finite()) {
+                    points[index].normal += normal.into_inner();
+                }
+            }
+        }
+        for point in &mut points {
+            point.normal = point
+                .normal
+                .try_normalize(f32::EPSILON)
+                .unwrap_or_else(Vector4::z);
+        }
+        // Vertices without faces have no normal to shade by, draw_points shows them head on
+        for v in &self.vertices {
+            if let Entry::Vacant(entry) = shared.entry(key(v)) {
+                entry.insert(points.len());
+                points.push(Point {
+                    position: *v,
+                    normal: Vector4::zeros(),
+                    color: (0xFF, 0xFF, 0xFF),
+                });
+            }
+        }
+        self.points = points;
+    }
+}
+
+// The box around several meshes
+pub fn bounding_box(meshes: &[SimpleMesh]) -> AABB {
+    let mut bounding_box = AABB::new(
+        Vector4::new(f32::MAX, f32::MAX, f32::MAX, 1.0),
+        Vector4::new(f32::MIN, f32::MIN, f32::MIN, 1.0),
+    );
+    for mesh in meshes {
+        bounding_box.min = bounding_box.min.inf(&mesh.bounding_box.min);
+        bounding_box.max = bounding_box.max.sup(&mesh.bounding_box.max);
+    }
+    bounding_box
+}
+
+// How far the meshes reach from the origin along any axis once placed, which auto-fit frames as the
+// turntable turns around the origin
+pub fn fit_extent(meshes: &[SimpleMesh]) -> f32 {
+    let mut extent: f32 = 0.0;
+    for mesh in meshes {
+        let (min, max) = (&mesh.bounding_box.min, &mesh.bounding_box.max);
+        for i in 0..8 {
+            // Every corner, a placement can turn any of them furthest out
+            let corner = mesh.placement
+                * Vector4::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                    1.0,
+                );
+            extent = extent.max(corner.xyz().amax());
+        }
+    }
+    extent
+}
+
+// Scales meshes uniformly about the origin, so the diagonal of the box around them is size long
+pub fn normalize_scale(meshes: &mut [SimpleMesh], size: f32) {
+    let bounds = bounding_box(meshes);
+    let diagonal = (bounds.max - bounds.min).xyz().norm();
+    if diagonal > 0.0 && diagonal.is_finite() {
+        for mesh in meshes {
+            mesh.mul(Matrix4::new_scaling(size / diagonal));
+        }
+    }
+}
+
+// Distinct colors handed out to groups in order
+const GROUP_PALETTE: &[(u8, u8, u8)] = &[
+    (230, 25, 75),
+    (60, 180, 75),
+    (255, 225, 25),
+    (0, 130, 200),
+    (245, 130, 48),
+    (145, 30, 180),
+    (70, 240, 240),
+    (240, 50, 230),
+    (210, 245, 60),
+    (250, 190, 190),
+    (0, 128, 128),
+    (170, 110, 40),
+];
+
+// Colors each mesh (an OBJ group or object) by its place among all the input files, so reloading
+// gives the same colors and no two files start at the same one
+pub fn color_by_group<'a>(meshes: impl IntoIterator<Item = &'a mut SimpleMesh>) {
+    for (index, mesh) in meshes.into_iter().enumerate() {
+        let color = GROUP_PALETTE[index % GROUP_PALETTE.len()];
+        for triangle in &mut mesh.triangles {
+            triangle.color = color;
+        }
+    }
+}
+
+// Centers both sets of meshes on the origin and places them next to each other, so each spins in place
+pub fn side_by_side(left: &mut [SimpleMesh], right: &mut [SimpleMesh]) {
+    let radius = |meshes: &[SimpleMesh]| {
+        let bounds = bounding_box(meshes);
+        (bounds.max - bounds.min).xyz().norm() / 2.0
+    };
+    let radius = radius(left).max(radius(right));
+    for (meshes, offset) in [(left, -radius), (right, radius)] {
+        let bounds = bounding_box(meshes);
+        let center = ((bounds.min + bounds.max) / 2.0).xyz();
+        for mesh in meshes.iter_mut() {
+            mesh.mul(Matrix4::new_translation(&-center));
+            mesh.placement = Matrix4::new_translation(&Vector3::new(offset, 0.0, 0.0));
+        }
+    }
+}
+
+// Turns meshes into a wireframe in a contrasting color, to be drawn over others
+pub fn overlay(meshes: &mut [SimpleMesh]) {
+    for mesh in meshes {
+        for triangle in &mut mesh.triangles {
+            triangle.color = (255, 64, 64);
+        }
+        mesh.wireframe = true;
+    }
+}
+
+// Rotates the meshes about their shared centroid so their principal axes line up with the world's,
+// the largest extent along X, the next along Z, and the smallest (up) along Y
+pub fn auto_orient(meshes: &mut [SimpleMesh]) {
+    let points: Vec<Vector3<f32>> = meshes
+        .iter()
+        .flat_map(|mesh| mesh.triangles.iter())
+        .flat_map(|tri| vec![tri.v1.xyz(), tri.v2.xyz(), tri.v3.xyz()])
+        .chain(
+            meshes
+                .iter()
+                .flat_map(|mesh| mesh.vertices.iter().map(|v| v.xyz())),
+        )
+        .collect();
+    if points.is_empty() {
+        return;
+    }
+    let centroid = points.iter().sum::<Vector3<f32>>() / points.len() as f32;
+    let covariance = points
+        .iter()
+        .map(|p| (p - centroid) * (p - centroid).transpose())
+        .sum::<Matrix3<f32>>()
+        / points.len() as f32;
+
+    let eigen = covariance.symmetric_eigen();
+    if eigen.eigenvalues.iter().any(|value| !value.is_finite()) {
+        return; // Non-finite vertices leave no axes to line up with
+    }
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| eigen.eigenvalues[b].total_cmp(&eigen.eigenvalues[a]));
+    // Eigenvectors have no sign, so point each so the bulk of the points sit on its negative side
+    // (a positive third moment), which sits most scans on their base instead of their head
+    let axis = |index: usize| -> Vector3<f32> {
+        let axis: Vector3<f32> = eigen.eigenvectors.column(order[index]).into();
+        let skew: f32 = points
+            .iter()
+            .map(|p| (p - centroid).dot(&axis).powi(3))
+            .sum();
+        if skew < 0.0 {
+            -axis
+        } else {
+            axis
+        }
+    };
+    let (x, y) = (axis(0), axis(2));
+    let z = x.cross(&y); // Keeps the rotation right handed, mirroring would flip the winding
+
+    let rotation = Matrix4::new(
+        x.x, x.y, x.z, 0.0, y.x, y.y, y.z, 0.0, z.x, z.y, z.z, 0.0, 0.0, 0.0, 0.0, 1.0,
+    );
+    let transform =
+        Matrix4::new_translation(&centroid) * rotation * Matrix4::new_translation(&-centroid);
+    for mesh in meshes {
+        mesh.mul(transform);
+    }
 }
 
 impl ToSimpleMeshWithMaterial for Mesh {
     fn to_simple_mesh_with_materials(&self, materials: &[Material]) -> SimpleMesh {
-        let mut bounding_box = AABB {
-            // This is the general bounding box for the mesh
-            min: Vector4::new(0.0, 0.0, 0.0, 1.0),
-            max: Vector4::new(0.0, 0.0, 0.0, 1.0),
-        };
+        // Polygons are split into fans, (the three corners' offsets into indices, which edges are diagonals)
+        let mut fans: Vec<([usize; 3], [bool; 3])> = vec![];
+        let mut start = 0;
+        for face in 0..self.num_face_indices.len() {
+            let count = self.num_face_indices[face] as usize; // Lines (2 indices) get no triangles
+            for i in 1..count.saturating_sub(1) {
+                fans.push((
+                    [start, start + i, start + i + 1],
+                    [i > 1, false, i + 2 < count],
+                ));
+            }
+            start += count;
+        }
         let mut triangles = vec![
             Triangle {
                 // Repeat this triangle for all faces in polygon
                 color: (1, 1, 1),
                 v1: Vector4::new(0.0, 0.0, 0.0, 1.0),
                 v2: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v3: Vector4::new(0.0, 0.0, 0.0, 1.0)
+                v3: Vector4::new(0.0, 0.0, 0.0, 1.0),
+                diagonals: [false; 3],
+                uvs: None,
             };
-            self.indices.len() / 3
+            fans.len()
         ];
         for (x, tri) in triangles.iter_mut().enumerate() {
-            tri.v1.x = self.positions[(self.indices[x * 3] * 3) as usize];
-            tri.v1.y = self.positions[(self.indices[x * 3] * 3 + 1) as usize];
-            tri.v1.z = self.positions[(self.indices[x * 3] * 3 + 2) as usize];
-            tri.v2.x = self.positions[(self.indices[x * 3 + 1] * 3) as usize];
-            tri.v2.y = self.positions[(self.indices[x * 3 + 1] * 3 + 1) as usize];
-            tri.v2.z = self.positions[(self.indices[x * 3 + 1] * 3 + 2) as usize];
-            tri.v3.x = self.positions[(self.indices[x * 3 + 2] * 3) as usize];
-            tri.v3.y = self.positions[(self.indices[x * 3 + 2] * 3 + 1) as usize];
-            tri.v3.z = self.positions[(self.indices[x * 3 + 2] * 3 + 2) as usize];
+            let (corners, diagonals) = fans[x];
+            tri.v1.x = self.positions[(self.indices[corners[0]] * 3) as usize];
+            tri.v1.y = self.positions[(self.indices[corners[0]] * 3 + 1) as usize];
+            tri.v1.z = self.positions[(self.indices[corners[0]] * 3 + 2) as usize];
+            tri.v2.x = self.positions[(self.indices[corners[1]] * 3) as usize];
+            tri.v2.y = self.positions[(self.indices[corners[1]] * 3 + 1) as usize];
+            tri.v2.z = self.positions[(self.indices[corners[1]] * 3 + 2) as usize];
+            tri.v3.x = self.positions[(self.indices[corners[2]] * 3) as usize];
+            tri.v3.y = self.positions[(self.indices[corners[2]] * 3 + 1) as usize];
+            tri.v3.z = self.positions[(self.indices[corners[2]] * 3 + 2) as usize];
+            tri.diagonals = diagonals;
+            if !self.texcoords.is_empty() {
+                let uv = |corner: usize| {
+                    let index = self.indices[corner] as usize;
+                    (self.texcoords[index * 2], self.texcoords[index * 2 + 1])
+                };
+                tri.uvs = Some([uv(corners[0]), uv(corners[1]), uv(corners[2])]);
+            }
 
             if !materials.is_empty() {
                 let material = &materials[self.material_id.unwrap()];
@@ -109,19 +488,17 @@ impl ToSimpleMeshWithMaterial for Mesh {
                     (material.diffuse[2] * 255.0) as u8,
                 );
             }
-
-            let aabb = tri.to_aabb(); // Compare this triangles aabb to the mesh's aabb
-            bounding_box.min.x = aabb.min.x.min(bounding_box.min.x);
-            bounding_box.min.y = aabb.min.y.min(bounding_box.min.y);
-            bounding_box.min.z = aabb.min.z.min(bounding_box.min.z);
-            bounding_box.max.x = aabb.max.x.max(bounding_box.max.x);
-            bounding_box.max.y = aabb.max.y.max(bounding_box.max.y);
-            bounding_box.max.z = aabb.max.z.max(bounding_box.max.z);
-        }
-        SimpleMesh {
-            triangles,
-            bounding_box,
         }
+        // Without faces the positions are all there is, a point cloud
+        let vertices: Vec<Vector4<f32>> = if self.indices.is_empty() {
+            self.positions
+                .chunks_exact(3)
+                .map(|p| Vector4::new(p[0], p[1], p[2], 1.0))
+                .collect()
+        } else {
+            vec![]
+        };
+        SimpleMesh::new(String::new(), triangles, vertices)
     }
 }
 
@@ -134,20 +511,18 @@ impl ToSimpleMesh for Mesh {
 /// Convert stl_io IndexedMesh into Sloth style triangles.
 impl ToSimpleMesh for stl_io::IndexedMesh {
     fn to_simple_mesh(&self) -> SimpleMesh {
-        let mut bounding_box = AABB {
-            min: Vector4::new(std::f32::MAX, std::f32::MAX, std::f32::MAX, 1.0),
-            max: Vector4::new(std::f32::MIN, std::f32::MIN, std::f32::MIN, 1.0),
-        };
         fn stlv2v4(stlio_vec: [f32; 3]) -> Vector4<f32> {
             Vector4::new(stlio_vec[0], stlio_vec[1], stlio_vec[2], 1.0)
-        };
+        }
         let mut triangles = vec![
             Triangle {
                 // at time of writing, stl_io lacked color
                 color: (0xFF, 0xFF, 0x00),
                 v1: Vector4::new(0.0, 0.0, 0.0, 1.0),
                 v2: Vector4::new(0.0, 0.0, 0.0, 1.0),
-                v3: Vector4::new(0.0, 0.0, 0.0, 1.0)
+                v3: Vector4::new(0.0, 0.0, 0.0, 1.0),
+                diagonals: [false; 3],
+                uvs: None,
             };
             self.faces.len()
         ];
@@ -157,17 +532,12 @@ impl ToSimpleMesh for stl_io::IndexedMesh {
             triangles[t_index].v1 = stlv2v4(self.vertices[self.faces[t_index].vertices[0]]);
             triangles[t_index].v2 = stlv2v4(self.vertices[self.faces[t_index].vertices[1]]);
             triangles[t_index].v3 = stlv2v4(self.vertices[self.faces[t_index].vertices[2]]);
-            let aabb = triangles[t_index].to_aabb();
-            bounding_box.min.x = aabb.min.x.min(bounding_box.min.x);
-            bounding_box.min.y = aabb.min.y.min(bounding_box.min.y);
-            bounding_box.min.z = aabb.min.z.min(bounding_box.min.z);
-            bounding_box.max.x = aabb.max.x.max(bounding_box.max.x);
-            bounding_box.max.y = aabb.max.y.max(bounding_box.max.y);
-            bounding_box.max.z = aabb.max.z.max(bounding_box.max.z);
-        }
-        SimpleMesh {
-            triangles,
-            bounding_box,
         }
+        let vertices: Vec<Vector4<f32>> = if self.faces.is_empty() {
+            self.vertices.iter().map(|&v| stlv2v4(v)).collect()
+        } else {
+            vec![]
+        };
+        SimpleMesh::new(String::new(), triangles, vertices)
     }
 }
@@ -0,0 +1,639 @@
+use crate::geometry::{SimpleMesh, Surface, Triangle};
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3, Vector4};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// A parsed JSON value
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+// What a missing key or index reads as
+static NULL: Json = Json::Null;
+
+impl Json {
+    fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+    fn at(&self, index: usize) -> &Json {
+        self.items().get(index).unwrap_or(&NULL)
+    }
+    fn items(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[],
+        }
+    }
+    fn boolean(&self) -> bool {
+        matches!(self, Json::Bool(true))
+    }
+    fn number(&self) -> Option<f64> {
+        match self {
+            Json::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+    fn index(&self) -> Option<usize> {
+        self.number()
+            .filter(|number| *number >= 0.0 && number.fract() == 0.0)
+            .map(|number| number as usize)
+    }
+    fn string(&self) -> Option<&str> {
+        match self {
+            Json::String(string) => Some(string),
+            _ => None,
+        }
+    }
+    // An array of N numbers, or the default when it's missing or another length
+    fn numbers<const N: usize>(&self, default: [f64; N]) -> [f64; N] {
+        let numbers: Vec<f64> = self.items().iter().filter_map(Json::number).collect();
+        let mut array = default;
+        if numbers.len() == N {
+            array.copy_from_slice(&numbers);
+        }
+        array
+    }
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn parse(bytes: &[u8]) -> Result<Json, Box<dyn Error>> {
+        let mut parser = Parser { bytes, at: 0 };
+        let value = parser.value()?;
+        match parser.peek() {
+            None => Ok(value),
+            Some(_) => Err(parser.error("there's more after the JSON")),
+        }
+    }
+    fn error(&self, message: &str) -> Box<dyn Error> {
+        format!("{} at byte {} of the JSON", message, self.at).into()
+    }
+    fn peek(&mut self) -> Option<u8> {
+        while self.bytes.get(self.at).is_some_and(u8::is_ascii_whitespace) {
+            self.at += 1;
+        }
+        self.bytes.get(self.at).copied()
+    }
+    fn next(&mut self) -> Result<u8, Box<dyn Error>> {
+        let byte = self
+            .peek()
+            .ok_or_else(|| self.error("the JSON ends early"))?;
+        self.at += 1;
+        Ok(byte)
+    }
+    fn expect(&mut self, byte: u8) -> Result<(), Box<dyn Error>> {
+        match self.next()? {
+            next if next == byte => Ok(()),
+            _ => Err(self.error(&format!("expected {}", byte as char))),
+        }
+    }
+    fn value(&mut self) -> Result<Json, Box<dyn Error>> {
+        match self.peek() {
+            Some(b'{') => {
+                self.at += 1;
+                let mut members = vec![];
+                if self.peek() == Some(b'}') {
+                    self.at += 1;
+                    return Ok(Json::Object(members));
+                }
+                loop {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    match self.next()? {
+                        b',' => {}
+                        b'}' => return Ok(Json::Object(members)),
+                        _ => return Err(self.error("expected , or }")),
+                    }
+                }
+            }
+            Some(b'[') => {
+                self.at += 1;
+                let mut items = vec![];
+                if self.peek() == Some(b']') {
+                    self.at += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    match self.next()? {
+                        b',' => {}
+                        b']' => return Ok(Json::Array(items)),
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+            }
+            Some(b'"') => Ok(Json::String(self.string()?)),
+            Some(b't') => self.word("true", Json::Bool(true)),
+            Some(b'f') => self.word("false", Json::Bool(false)),
+            Some(b'n') => self.word("null", Json::Null),
+            Some(_) => {
+                let start = self.at;
+                while self
+                    .bytes
+                    .get(self.at)
+                    .is_some_and(|byte| b"+-.eE0123456789".contains(byte))
+                {
+                    self.at += 1;
+                }
+                std::str::from_utf8(&self.bytes[start..self.at])?
+                    .parse()
+                    .map(Json::Number)
+                    .map_err(|_| self.error("expected a value"))
+            }
+            None => Err(self.error("the JSON ends early")),
+        }
+    }
+    fn word(&mut self, word: &str, value: Json) -> Result<Json, Box<dyn Error>> {
+        if self.bytes[self.at..].starts_with(word.as_bytes()) {
+            self.at += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("expected a value"))
+        }
+    }
+    fn string(&mut self) -> Result<String, Box<dyn Error>> {
+        self.expect(b'"')?;
+        let mut text = vec![];
+        loop {
+            let byte = *self
+                .bytes
+                .get(self.at)
+                .ok_or_else(|| self.error("a string isn't closed"))?;
+            self.at += 1;
+            match byte {
+                b'"' => return Ok(String::from_utf8(text)?),
+                b'\\' => {
+                    let escaped = *self
+                        .bytes
+                        .get(self.at)
+                        .ok_or_else(|| self.error("a string isn't closed"))?;
+                    self.at += 1;
+                    let character = match escaped {
+                        b'n' => '\n',
+                        b't' => '\t',
+                        b'r' => '\r',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'u' => {
+                            let mut code = self.hex()?;
+                            // Characters past the first plane are split in two
+                            if (0xD800..0xDC00).contains(&code)
+                                && self.bytes[self.at..].starts_with(b"\\u")
+                            {
+                                self.at += 2;
+                                code = 0x10000 + ((code - 0xD800) << 10) + (self.hex()? - 0xDC00);
+                            }
+                            std::char::from_u32(code).unwrap_or(std::char::REPLACEMENT_CHARACTER)
+                        }
+                        other => other as char,
+                    };
+                    let mut encoded = [0; 4];
+                    text.extend_from_slice(character.encode_utf8(&mut encoded).as_bytes());
+                }
+                _ => text.push(byte),
+            }
+        }
+    }
+    fn hex(&mut self) -> Result<u32, Box<dyn Error>> {
+        let digits = self
+            .bytes
+            .get(self.at..self.at + 4)
+            .ok_or_else(|| self.error("a \\u escape is cut short"))?;
+        self.at += 4;
+        Ok(u32::from_str_radix(std::str::from_utf8(digits)?, 16)?)
+    }
+}
+
+// The bytes of a base64 data URI's payload
+fn decode_base64(text: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut bytes = vec![];
+    let (mut bits, mut count) = (0u32, 0);
+    for c in text.bytes().filter(|&c| c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return Err("a data URI isn't base64".into()),
+        };
+        bits = (bits << 6 | u32::from(value)) & 0xFFFF;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            bytes.push((bits >> count) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Result<u32, Box<dyn Error>> {
+    let mut word = [0; 4];
+    word.copy_from_slice(bytes.get(at..at + 4).ok_or("the file is truncated")?);
+    Ok(u32::from_le_bytes(word))
+}
+
+// The JSON and the binary chunk of a .glb file
+type Chunks<'a> = (&'a [u8], Option<&'a [u8]>);
+
+fn split_glb(bytes: &[u8]) -> Result<Chunks<'_>, Box<dyn Error>> {
+    let length = (le_u32(bytes, 8)? as usize).min(bytes.len());
+    let (mut json, mut binary) = (None, None);
+    let mut at = 12;
+    while at + 8 <= length {
+        let size = le_u32(bytes, at)? as usize;
+        let chunk = bytes
+            .get(at + 8..at + 8 + size)
+            .ok_or("the file is truncated")?;
+        match le_u32(bytes, at + 4)? {
+            0x4E4F_534A => json = json.or(Some(chunk)),
+            0x004E_4942 => binary = binary.or(Some(chunk)),
+            _ => {} // Chunks of extensions we don't know are skipped
+        }
+        at += 8 + size;
+    }
+    Ok((json.ok_or("the file has no JSON chunk")?, binary))
+}
+
+// Reads a component from its little endian bytes
+type ReadComponent = fn(&[u8]) -> f64;
+
+struct Gltf {
+    json: Json,
+    buffers: Vec<Vec<u8>>,
+}
+
+impl Gltf {
+    fn load(path: &Path) -> Result<Gltf, Box<dyn Error>> {
+        let bytes = fs::read(path)?;
+        let (json, binary) = if bytes.starts_with(b"glTF") {
+            split_glb(&bytes)?
+        } else {
+            (&bytes[..], None)
+        };
+        let json = Parser::parse(json)?;
+        let mut buffers = vec![];
+        for buffer in json.get("buffers").items() {
+            buffers.push(match buffer.get("uri").string() {
+                // Only the file's own binary chunk has no URI
+                None => binary.ok_or("a buffer has no URI")?.to_vec(),
+                Some(uri) if uri.starts_with("data:") => match uri.find(";base64,") {
+                    Some(start) => decode_base64(&uri[start + 8..])?,
+                    None => return Err("a data URI isn't base64".into()),
+                },
+                Some(uri) => {
+                    let file = path.with_file_name(uri);
+                    fs::read(&file).map_err(|e| {
+                        format!("the buffer {} couldn't be read, {}", file.display(), e)
+                    })?
+                }
+            });
+        }
+        Ok(Gltf { json, buffers })
+    }
+    // The elements of an accessor as numbers, with how many numbers each element has
+    fn accessor(&self, index: usize) -> Result<(Vec<f64>, usize), Box<dyn Error>> {
+        let accessor = self.json.get("accessors").at(index);
+        let components = match accessor.get("type").string() {
+            Some("SCALAR") => 1,
+            Some("VEC2") => 2,
+            Some("VEC3") => 3,
+            Some("VEC4") => 4,
+            _ => return Err(format!("accessor {} isn't a scalar or a vector", index).into()),
+        };
+        // Normalized integers stand for -1.0 to 1.0 or 0.0 to 1.0, their largest value is 1.0
+        let (size, largest, read): (usize, f64, ReadComponent) =
+            match accessor.get("componentType").index() {
+                Some(5120) => (1, 127.0, |b| f64::from(b[0] as i8)),
+                Some(5121) => (1, 255.0, |b| f64::from(b[0])),
+                Some(5122) => (2, 32767.0, |b| f64::from(i16::from_le_bytes([b[0], b[1]]))),
+                Some(5123) => (2, 65535.0, |b| f64::from(u16::from_le_bytes([b[0], b[1]]))),
+                Some(5125) => (4, 1.0, |b| {
+                    f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                }),
+                Some(5126) => (4, 1.0, |b| {
+                    f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                }),
+                _ => return Err(format!("accessor {} has an unknown component type", index).into()),
+            };
+        let normalized = accessor.get("normalized").boolean();
+        let count = accessor.get("count").index().unwrap_or(0);
+        let view = match accessor.get("bufferView").index() {
+            Some(view) => self.json.get("bufferViews").at(view),
+            None => return Ok((vec![0.0; count * components], components)), // All zeros
+        };
+        let buffer = view
+            .get("buffer")
+            .index()
+            .and_then(|buffer| self.buffers.get(buffer))
+            .ok_or_else(|| format!("accessor {} is in a buffer that doesn't exist", index))?;
+        let start = view.get("byteOffset").index().unwrap_or(0)
+            + accessor.get("byteOffset").index().unwrap_or(0);
+        let stride = view.get("byteStride").index().unwrap_or(size * components);
+        let end = view.get("byteOffset").index().unwrap_or(0)
+            + view.get("byteLength").index().unwrap_or(0);
+        let mut values = Vec::with_capacity((count * components).min(buffer.len()));
+        for element in 0..count {
+            for component in 0..components {
+                let at = start + element * stride + component * size;
+                if at + size > end.min(buffer.len()) {
+                    return Err(format!("accessor {} reads past its buffer", index).into());
+                }
+                let value = read(&buffer[at..at + size]);
+                values.push(if normalized {
+                    (value / largest).max(-1.0)
+                } else {
+                    value
+                });
+            }
+        }
+        Ok((values, components))
+    }
+    // A material's color, and how it takes light
+    fn material(&self, index: Option<usize>) -> ((u8, u8, u8), Surface) {
+        let material = index.map_or(&NULL, |index| self.json.get("materials").at(index));
+        let extensions = material.get("extensions");
+        let channel = |x: f64| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+        let base = material
+            .get("pbrMetallicRoughness")
+            .get("baseColorFactor")
+            .numbers([1.0; 4]);
+        let emissive = material.get("emissiveFactor").numbers([0.0; 3]);
+        let strength = extensions
+            .get("KHR_materials_emissive_strength")
+            .get("emissiveStrength")
+            .number()
+            .unwrap_or(1.0);
+        (
+            (channel(base[0]), channel(base[1]), channel(base[2])),
+            Surface {
+                emissive: (
+                    channel(emissive[0] * strength),
+                    channel(emissive[1] * strength),
+                    channel(emissive[2] * strength),
+                ),
+                unlit: !matches!(extensions.get("KHR_materials_unlit"), Json::Null),
+            },
+        )
+    }
+    // A mesh for every triangle primitive of a glTF mesh, placed by its node
+    fn meshes(
+        &self,
+        mesh: usize,
+        transform: &Matrix4<f32>,
+        meshes: &mut Vec<SimpleMesh>,
+    ) -> Result<(), Box<dyn Error>> {
+        // Mirroring nodes turn the faces inside out, their winding is swapped back
+        let mirrored = transform.determinant() < 0.0;
+        let primitives = self.json.get("meshes").at(mesh).get("primitives");
+        for primitive in primitives.items() {
+            if primitive.get("mode").index().unwrap_or(4) != 4 {
+                continue; // Only triangle lists, not points, lines or strips
+            }
+            let position = primitive
+                .get("attributes")
+                .get("POSITION")
+                .index()
+                .ok_or("a primitive has no POSITION")?;
+            let (positions, components) = self.accessor(position)?;
+            if components != 3 {
+                return Err("a primitive's POSITION isn't a VEC3".into());
+            }
+            let vertices: Vec<Vector4<f32>> = positions
+                .chunks_exact(3)
+                .map(|p| transform * Vector4::new(p[0] as f32, p[1] as f32, p[2] as f32, 1.0))
+                .collect();
+            let indices: Vec<usize> = match primitive.get("indices").index() {
+                Some(indices) => self
+                    .accessor(indices)?
+                    .0
+                    .iter()
+                    .map(|&i| i as usize)
+                    .collect(),
+                None => (0..vertices.len()).collect(),
+            };
+            let (color, surface) = self.material(primitive.get("material").index());
+            let mut triangles = vec![];
+            for face in indices.chunks_exact(3) {
+                let corner = |i: usize| {
+                    vertices.get(face[i]).copied().ok_or_else(|| {
+                        format!("index {} is past the {} vertices", face[i], vertices.len())
+                    })
+                };
+                let (v2, v3) = if mirrored {
+                    (corner(2)?, corner(1)?)
+                } else {
+                    (corner(1)?, corner(2)?)
+                };
+                triangles.push(Triangle {
+                    color,
+                    v1: corner(0)?,
+                    v2,
+                    v3,
+                    diagonals: [false; 3],
+                    uvs: None,
+                });
+            }
+            let mut mesh = SimpleMesh::new(String::new(), triangles, vec![]);
+            mesh.surface = surface;
+            meshes.push(mesh);
+        }
+        Ok(())
+    }
+    // Places the meshes of a node and its children, depth keeps a node that's its own child from looping
+    fn node(
+        &self,
+        index: usize,
+        parent: &Matrix4<f32>,
+        depth: usize,
+        meshes: &mut Vec<SimpleMesh>,
+    ) -> Result<(), Box<dyn Error>> {
+        let nodes = self.json.get("nodes");
+        if depth > nodes.items().len() {
+            return Err("the nodes are children of each other".into());
+        }
+        let node = nodes.at(index);
+        let f32s = |numbers: &[f64]| numbers.iter().map(|&x| x as f32).collect::<Vec<_>>();
+        let local = match node.get("matrix").items().len() {
+            16 => Matrix4::from_column_slice(&f32s(&node.get("matrix").numbers([0.0; 16]))),
+            _ => {
+                let t = f32s(&node.get("translation").numbers([0.0; 3]));
+                let r = f32s(&node.get("rotation").numbers([0.0, 0.0, 0.0, 1.0]));
+                let s = f32s(&node.get("scale").numbers([1.0; 3]));
+                Matrix4::new_translation(&Vector3::new(t[0], t[1], t[2]))
+                    * UnitQuaternion::from_quaternion(Quaternion::new(r[3], r[0], r[1], r[2]))
+                        .to_homogeneous()
+                    * Matrix4::new_nonuniform_scaling(&Vector3::new(s[0], s[1], s[2]))
+            }
+        };
+        let transform = parent * local;
+        if let Some(mesh) = node.get("mesh").index() {
+            self.meshes(mesh, &transform, meshes)?;
+        }
+        for child in node.get("children").items() {
+            if let Some(child) = child.index() {
+                self.node(child, &transform, depth + 1, meshes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Loads the triangles of a .gltf or .glb file's scene, a mesh for each primitive with its
+// material's base color, emissive color and whether it's unlit
+pub fn load_gltf(path: &Path) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let gltf = Gltf::load(path)?;
+    let mut meshes = vec![];
+    let scene = gltf.json.get("scene").index().unwrap_or(0);
+    match gltf.json.get("scenes").at(scene) {
+        // Without a scene every mesh is shown where it is
+        Json::Null => {
+            for mesh in 0..gltf.json.get("meshes").items().len() {
+                gltf.meshes(mesh, &Matrix4::identity(), &mut meshes)?;
+            }
+        }
+        scene => {
+            for node in scene.get("nodes").items().iter().filter_map(Json::index) {
+                gltf.node(node, &Matrix4::identity(), 0, &mut meshes)?;
+            }
+        }
+    }
+    if meshes.is_empty() {
+        return Err("the scene has no triangles".into());
+    }
+    Ok(meshes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // A triangle at (0, 0, 0), (1, 0, 0) and (0, 1, 0), then its indices as unsigned shorts
+    const TRIANGLE: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAABAAIA";
+
+    // The same two primitives, as a .gltf with the data inline or a .glb with it in its chunk
+    fn document(uri: &str) -> String {
+        format!(
+            r#"{{
+                "asset": {{ "version": "2.0" }},
+                "scene": 0,
+                "scenes": [{{ "nodes": [0] }}],
+                "nodes": [{{ "children": [1] }}, {{ "mesh": 0, "translation": [5, 0, 0] }}],
+                "meshes": [{{ "primitives": [
+                    {{ "attributes": {{ "POSITION": 0 }}, "indices": 1, "material": 0 }},
+                    {{ "attributes": {{ "POSITION": 0 }}, "material": 1 }}
+                ] }}],
+                "materials": [
+                    {{
+                        "pbrMetallicRoughness": {{ "baseColorFactor": [1, 0, 0, 1] }},
+                        "emissiveFactor": [0, 0.5, 0],
+                        "extensions": {{ "KHR_materials_emissive_strength": {{ "emissiveStrength": 2 }} }}
+                    }},
+                    {{ "name": "flat \"é\"", "extensions": {{ "KHR_materials_unlit": {{}} }} }}
+                ],
+                "buffers": [{{ {}"byteLength": 42 }}],
+                "bufferViews": [
+                    {{ "buffer": 0, "byteLength": 36 }},
+                    {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+                ],
+                "accessors": [
+                    {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" }},
+                    {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+                ]
+            }}"#,
+            uri
+        )
+    }
+
+    // Goes through a file of its own, the tests run at the same time
+    fn load(bytes: &[u8], extension: &str) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "sloth-test-{}-{}.{}",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        fs::write(&path, bytes)?;
+        let meshes = load_gltf(&path);
+        fs::remove_file(&path)?;
+        meshes
+    }
+
+    fn check(meshes: &[SimpleMesh]) {
+        assert_eq!(meshes.len(), 2);
+        for mesh in meshes {
+            let triangle = &mesh.triangles[0];
+            assert_eq!(mesh.triangles.len(), 1);
+            assert_eq!(triangle.v1, Vector4::new(5.0, 0.0, 0.0, 1.0)); // Moved by its node
+            assert_eq!(triangle.v2, Vector4::new(6.0, 0.0, 0.0, 1.0));
+        }
+        assert_eq!(meshes[0].triangles[0].color, (255, 0, 0));
+        assert_eq!(meshes[0].surface.emissive, (0, 255, 0));
+        assert!(!meshes[0].surface.unlit);
+        assert_eq!(meshes[1].triangles[0].color, (255, 255, 255));
+        assert_eq!(meshes[1].surface.emissive, (0, 0, 0));
+        assert!(meshes[1].surface.unlit);
+    }
+
+    #[test]
+    fn gltf_and_glb_files_load_the_same() {
+        let gltf = document(&format!(
+            r#""uri": "data:application/octet-stream;base64,{}", "#,
+            TRIANGLE
+        ));
+        check(&load(gltf.as_bytes(), "gltf").unwrap());
+
+        let mut json = document("").into_bytes();
+        json.resize(json.len().div_ceil(4) * 4, b' '); // Chunks are padded to 4 bytes
+        let mut binary = decode_base64(TRIANGLE).unwrap();
+        binary.resize(44, 0);
+        let mut glb = b"glTF".to_vec();
+        glb.extend_from_slice(&2u32.to_le_bytes());
+        glb.extend_from_slice(&((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+        glb.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"JSON");
+        glb.extend_from_slice(&json);
+        glb.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+        glb.extend_from_slice(b"BIN\0");
+        glb.extend_from_slice(&binary);
+        check(&load(&glb, "glb").unwrap());
+    }
+
+    #[test]
+    fn broken_files_say_what_is_wrong() {
+        let error = |text: &str| match load(text.as_bytes(), "gltf") {
+            Err(e) => e.to_string(),
+            Ok(_) => panic!("loaded a broken file"),
+        };
+        assert!(error(r#"{ "meshes": [ }"#).contains("expected a value"));
+        assert!(error(r#"{ "meshes": [] }"#).contains("no triangles"));
+        let past = document(&format!(
+            r#""uri": "data:application/octet-stream;base64,{}", "#,
+            TRIANGLE
+        ))
+        .replace(
+            r#""count": 3, "type": "SCALAR""#,
+            r#""count": 6, "type": "SCALAR""#,
+        );
+        assert!(error(&past).contains("reads past its buffer"));
+    }
+}
@@ -1,9 +1,28 @@
-use crate::context::Context;
-use crate::geometry::{SimpleMesh, ToSimpleMesh, ToSimpleMeshWithMaterial};
-use clap::{App, Arg, ArgMatches, SubCommand};
+use crate::background::{query_terminal_background, Background};
+use crate::cache::{read_cache, write_cache};
+use crate::context::{Context, DepthTiebreak};
+use crate::decimate::decimate;
+use crate::geometry::{
+    auto_orient, color_by_group, normalize_scale, overlay, side_by_side, SimpleMesh, Spin,
+    ToSimpleMesh, ToSimpleMeshWithMaterial, Triangle,
+};
+use crate::gltf::load_gltf;
+use crate::msh::{estimate_triangles, load_msh};
+use crate::ply::{estimate_ply_triangles, load_ply};
+use crate::png::Rgb;
+use crate::rasterizer::{BuiltinShader, Falloff, PointStyle};
+use crate::scene::Camera;
+use crate::terminal::LoadingIndicator;
+use crate::texture::{Texture, TextureFilter, TextureWrap};
+use crate::timing::FramePacer;
+use crate::views::{view_preset, Transition};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use nalgebra::{Unit, Vector3};
 use std::error::Error;
-use std::fs::OpenOptions;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader};
 use std::path::Path;
+use std::time::Duration;
 
 pub fn cli_matches<'a>() -> ArgMatches<'a> {
     commands_for_subcommands(
@@ -11,6 +30,7 @@ pub fn cli_matches<'a>() -> ArgMatches<'a> {
             .version("0.1")
             .author("Mitchell Hynes. <mshynes@mun.ca>")
             .about("A toy for rendering 3D objects in the command line")
+            .setting(AppSettings::SubcommandsNegateReqs)
             .subcommand(commands_for_subcommands(
                 SubCommand::with_name("image")
                     .about("Generates a colorless terminal output as lines of text")
@@ -34,6 +54,128 @@ pub fn cli_matches<'a>() -> ArgMatches<'a> {
                             .short("h")
                             .help("Sets the height of the image to generate")
                             .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("verbose")
+                            .long("verbose")
+                            .help("Prints rendering statistics to stderr"),
+                    )
+                    .arg(
+                        Arg::with_name("depth png")
+                            .long("depth-png")
+                            .help("Writes the normalized depth buffer to a 16-bit grayscale PNG, near is white")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("normal png")
+                            .long("normal-png")
+                            .help("Writes the view-space normals to an RGB PNG, x right, y up and z towards the camera")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("solid png")
+                            .long("solid-png")
+                            .help("Writes the shaded meshes without any lines to an RGBA PNG, transparent where nothing was drawn")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("wire png")
+                            .long("wire-png")
+                            .help("Writes only the wireframe and feature edge lines to an RGBA PNG, to composite over --solid-png")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("color png")
+                            .long("color-png")
+                            .help("Writes the frame as it's shown, over its background, to an RGBA PNG")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("braille file")
+                            .long("render-to-braille-file")
+                            .help("Writes the frame as width by height braille characters to a text file, 2 by 4 dots in each")
+                            .value_name("path")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("transparent color")
+                            .long("transparent-color")
+                            .help("Leaves the pixels of --color-png within tolerance (0 unless it's given) of this color on every channel transparent, to key out a solid background")
+                            .value_name("r,g,b[,tolerance]")
+                            .takes_value(true)
+                            .requires("color png"),
+                    )
+                    .arg(
+                        Arg::with_name("background image")
+                            .long("background-image")
+                            .help("Composites the render over a PPM image, scaled to fit")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("progressive")
+                            .long("progressive")
+                            .help("Renders coarse previews first, rewriting this file with every sharper pass")
+                            .value_name("path")
+                            .takes_value(true)
+                            .conflicts_with("frame count"),
+                    )
+                    .arg(
+                        Arg::with_name("transition")
+                            .long("transition")
+                            .help("Generates a web render easing the camera between these views, e.g. front,iso,top (front, back, left, right, top, bottom or iso)")
+                            .value_name("views")
+                            .takes_value(true)
+                            .conflicts_with_all(&["frame count", "progressive"]),
+                    )
+                    .arg(
+                        Arg::with_name("transition frames")
+                            .long("transition-frames")
+                            .help("Sets how many frames the whole transition takes, 60 if not given")
+                            .value_name("frames")
+                            .takes_value(true)
+                            .requires("transition"),
+                    )
+                    .arg(
+                        Arg::with_name("separate outputs")
+                            .long("separate-outputs")
+                            .help("Fits and draws every input file on its own, into a text file named after it in this directory, numbered where names repeat")
+                            .value_name("dir")
+                            .takes_value(true)
+                            .conflicts_with_all(&[
+                                "frame count",
+                                "transition",
+                                "progressive",
+                                "depth png",
+                                "normal png",
+                                "solid png",
+                                "wire png",
+                            ]),
+                    ),
+            ))
+            .subcommand(commands_for_subcommands(
+                SubCommand::with_name("compare")
+                    .about("Renders two models side by side, spinning together")
+                    .arg(
+                        Arg::with_name("before")
+                            .help("Sets the first file to compare")
+                            .required(true)
+                            .index(1),
+                    )
+                    .arg(
+                        Arg::with_name("after")
+                            .help("Sets the second file to compare")
+                            .required(true)
+                            .index(2),
+                    )
+                    .arg(
+                        Arg::with_name("overlay")
+                            .long("overlay")
+                            .help("Draws the second model as a wireframe over the first instead"),
                     ),
             ))
             .arg(
@@ -42,13 +184,227 @@ pub fn cli_matches<'a>() -> ArgMatches<'a> {
                     .required(true)
                     .multiple(true)
                     .index(1),
+            )
+            .arg(
+                Arg::with_name("max fps vsync")
+                    .long("max-fps-vsync")
+                    .help("Paces frames evenly at this FPS, smoothing out jitter from slow frames")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("stdin commands")
+                    .long("stdin-commands")
+                    .help("Reads rotate, speed, load, screenshot and quit commands from stdin, one per line"),
+            )
+            .arg(
+                Arg::with_name("color by group")
+                    .long("color-by-group")
+                    .help("Colors every OBJ group or object from a palette, ignoring materials"),
+            )
+            .arg(
+                Arg::with_name("normalize scale")
+                    .long("normalize-scale")
+                    .help("Scales each input file so its bounding box diagonal is this long")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("decimate error")
+                    .long("decimate-error")
+                    .help("Simplifies each input file for as long as its surface moves less than this fraction of its bounding box diagonal")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("max memory")
+                    .long("max-memory")
+                    .help("Refuses to load input files estimated to need more than this many megabytes")
+                    .value_name("MB")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("render scale")
+                    .long("render-scale")
+                    .help("Renders this many times the terminal's cells along each axis, shown as colored half blocks")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("cache")
+                    .long("cache")
+                    .help("Keeps every loaded and preprocessed input file in a binary .slothcache next to it, and loads that while it's newer"),
+            )
+            .arg(
+                Arg::with_name("face id property")
+                    .long("face-id-property")
+                    .help("Splits a PLY's faces by this integer face property, each segment colored from a palette")
+                    .value_name("name")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("auto orient")
+                    .long("auto-orient")
+                    .help("Rotates the scene upright, with its largest extent horizontal"),
             ),
     )
     .get_matches()
 }
 
 fn commands_for_subcommands<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
-    command_flag_color(command_rotates(app))
+    command_flips(command_shades(command_flag_color(command_rotates(app))))
+}
+
+fn command_flips<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("flip x")
+            .long("flip-output-x")
+            .help("Mirrors the output left to right"),
+    )
+    .arg(
+        Arg::with_name("flip y")
+            .long("flip-output-y")
+            .help("Mirrors the output top to bottom"),
+    )
+}
+
+const SHADERS: &[&str] = &["default", "matcap", "checker", "density"];
+
+fn command_shades<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
+    app.arg(
+        Arg::with_name("shader")
+            .short("s")
+            .long("shader")
+            .help("Sets the shader used to render every mesh")
+            .possible_values(SHADERS)
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("shader for")
+            .long("shader-for")
+            .help("Sets the shader of the meshes loaded from one input filename")
+            .value_names(&["filename", "shader"])
+            .number_of_values(2)
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("feature edges")
+            .long("feature-edges")
+            .help("Draws only silhouette edges and creases sharper than this angle (in degrees)")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("hide diagonals")
+            .long("hide-diagonals")
+            .help("Leaves out the edges added splitting quads and polygons into triangles"),
+    )
+    .arg(
+        Arg::with_name("checker size")
+            .long("checker-size")
+            .help("Sets the size of the checker shader's squares, in texture coordinates when the mesh has them and model units otherwise (default 0.25)")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("backface color")
+            .long("backface-color")
+            .help("Draws back faces in this r,g,b color instead of culling them")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("wire color by depth")
+            .long("wire-color-by-depth")
+            .help("Colors edges from the near r,g,b color to the far one by their depth")
+            .value_names(&["near", "far"])
+            .number_of_values(2),
+    )
+    .arg(
+        Arg::with_name("depth tiebreak")
+            .long("depth-tiebreak")
+            .help("Picks the fragment kept where coplanar faces meet at equal depth, the first drawn by default")
+            .possible_values(&["first", "last", "brightest"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("coverage blend")
+            .long("coverage-blend")
+            .help("Averages the colors of coplanar faces at equal depth, so overlapping decals don't flicker")
+            .conflicts_with("depth tiebreak"),
+    )
+    .arg(
+        Arg::with_name("points")
+            .long("points")
+            .help("Draws only the meshes' vertices, as dots"),
+    )
+    .arg(
+        Arg::with_name("point size")
+            .long("point-size")
+            .help("Sets how many pixels across the dots of --points are (default 1)")
+            .value_name("size")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("splat size")
+            .long("splat-size")
+            .help("Grows the nearest points up to this many pixels across, to fill the gaps in sparse clouds")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("splat falloff")
+            .long("splat-falloff")
+            .help("Sets the shape of the points, gaussian fades them out towards their edge")
+            .possible_values(&["square", "circle", "gaussian"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("fill char")
+            .long("fill-char")
+            .help("Draws the inside of every triangle with this character instead of by shade")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("edge char")
+            .long("edge-char")
+            .help("Draws the pixels within half a pixel of every triangle's edges with this character instead of by shade")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("adapt background")
+            .long("adapt-background")
+            .help("Asks the terminal for its background color, to draw over it and keep the shading readable on light themes"),
+    )
+    .arg(
+        Arg::with_name("rainbow")
+            .long("rainbow")
+            .help("Cycles the colors' hue over time"),
+    )
+    .arg(
+        Arg::with_name("rainbow speed")
+            .long("rainbow-speed")
+            .help("Sets how many degrees a second --rainbow turns the hue by (default 90)")
+            .value_name("degrees")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("bbox only")
+            .long("bbox-only")
+            .help("Draws only the meshes' bounding boxes, toggled with b while rendering"),
+    )
+    .arg(
+        Arg::with_name("matcap")
+            .long("matcap")
+            .help("Sets the PPM sphere image the matcap shader looks colors up in")
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("texture filter")
+            .long("texture-filter")
+            .help("Samples images between their pixels with the nearest one, or a bilinear blend by default")
+            .possible_values(&["nearest", "bilinear"])
+            .takes_value(true),
+    )
+    .arg(
+        Arg::with_name("texture wrap")
+            .long("texture-wrap")
+            .help("Samples images outside their edges tiled by default, stretched out with clamp, or mirrored")
+            .possible_values(&["repeat", "clamp", "mirror"])
+            .takes_value(true),
+    )
 }
 
 fn command_flag_color<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
@@ -81,6 +437,33 @@ fn command_rotates<'a, 'b>(app: App<'a, 'b>) -> App<'a, 'b> {
             .help("Sets the object's static Z rotation (in radians)")
             .takes_value(true),
     )
+    .arg(
+        Arg::with_name("seed rotation")
+            .long("seed-rotation-from-filename")
+            .help("Picks a reproducible initial rotation seeded from the input filename(s)"),
+    )
+    .arg(
+        Arg::with_name("spin for")
+            .long("spin-for")
+            .help("Spins the meshes loaded from one input filename on their own, e.g. models/suzy.obj 0,1,0 @2.0")
+            .value_names(&["filename", "axis", "@speed"])
+            .number_of_values(3)
+            .multiple(true),
+    )
+    .arg(
+        Arg::with_name("isometric")
+            .long("isometric")
+            .help(
+                "Holds the camera still at the true isometric angle, 120 degrees between the axes",
+            )
+            .conflicts_with_all(&["x", "y", "z", "seed rotation"]),
+    )
+    .arg(
+        Arg::with_name("zoom")
+            .long("zoom")
+            .help("Scales the scene this many times its auto-fit size, above 1.0 crops it tighter")
+            .takes_value(true),
+    )
 }
 
 pub fn to_meshes(models: Vec<tobj::Model>, materials: Vec<tobj::Material>) -> Vec<SimpleMesh> {
@@ -91,41 +474,333 @@ pub fn to_meshes(models: Vec<tobj::Model>, materials: Vec<tobj::Material>) -> Ve
     meshes
 }
 
-pub fn match_meshes(matches: &ArgMatches) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
-    let mut mesh_queue: Vec<SimpleMesh> = vec![];
-    for slice in matches.value_of("input filename(s)").unwrap().split(' ') {
-        let error = |s: &str, e: &str| -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
-            Err(format!("filename: [{}] couldn't load, {}. {}", slice, s, e).into())
-        };
-        // Fill list with file inputs (Splits for spaces -> multiple files)
-        let path = Path::new(slice);
-        let meshes = match path.extension() {
-            None => error("couldn't determine filename extension", ""),
-            Some(ext) => match ext.to_str() {
-                None => error("couldn't parse filename extension", ""),
-                Some(extstr) => match &*extstr.to_lowercase() {
-                    "obj" => match tobj::load_obj(&path, true) {
-                        Err(e) => error("tobj couldnt load/parse OBJ", &e.to_string()),
-                        Ok(present) => Ok(to_meshes(present.0, present.1)),
-                    },
-                    "stl" => match OpenOptions::new().read(true).open(&path) {
-                        Err(e) => error("STL load failed", &e.to_string()),
-                        Ok(mut file) => match stl_io::read_stl(&mut file) {
-                            Err(e) => error("stl_io couldnt parse STL", &e.to_string()),
-                            Ok(stlio_mesh) => Ok(vec![stlio_mesh.to_simple_mesh()]),
-                        },
+// Rewrites the OBJ quirks older exporters leave behind into lines tobj parses: a byte order mark,
+// lines continued with a trailing backslash, and comments after the data on a line
+pub fn normalize_obj(source: &str) -> String {
+    let mut normalized = String::with_capacity(source.len());
+    for line in source.trim_start_matches('\u{feff}').lines() {
+        // A # starts a comment at the start of a line or after whitespace, names like mat#1 keep it
+        let comment = line
+            .char_indices()
+            .find(|&(i, c)| c == '#' && (i == 0 || line[..i].ends_with(char::is_whitespace)));
+        let data = comment.map_or(line, |(i, _)| &line[..i]).trim_end();
+        match data.strip_suffix('\\') {
+            Some(continued) => {
+                normalized.push_str(continued);
+                normalized.push(' ');
+            }
+            None => {
+                normalized.push_str(data);
+                normalized.push('\n');
+            }
+        }
+    }
+    normalized
+}
+
+fn load_obj(path: &Path) -> tobj::LoadResult {
+    let source = match fs::read(path) {
+        Ok(source) => source,
+        Err(_) => return Err(tobj::LoadError::OpenFileFailed),
+    };
+    let normalized = normalize_obj(&String::from_utf8_lossy(&source));
+    let (mut models, materials) =
+        tobj::load_obj_buf(&mut normalized.as_bytes(), false, |mtl_path| {
+            // Material libraries are found next to the OBJ, like tobj::load_obj does
+            tobj::load_mtl(
+                path.parent()
+                    .map_or(mtl_path.to_owned(), |dir| dir.join(mtl_path)),
+            )
+        })?;
+    // tobj only keeps the positions faces use, a point cloud has none so its are read here
+    if models.iter().all(|model| model.mesh.indices.is_empty()) {
+        if let Some(model) = models.last_mut() {
+            for line in normalized.lines() {
+                let mut tokens = line.split_whitespace();
+                if tokens.next() != Some("v") {
+                    continue;
+                }
+                let position: Vec<f32> = tokens.take(3).filter_map(|t| t.parse().ok()).collect();
+                if position.len() != 3 {
+                    return Err(tobj::LoadError::PositionParseError);
+                }
+                model.mesh.positions.extend(position);
+            }
+        }
+    }
+    Ok((models, materials))
+}
+
+// Loads every mesh in one file, OBJs can hold several. face_id splits a PLY's faces by that property
+pub fn load_meshes(
+    filename: &str,
+    face_id: Option<&str>,
+) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let error = |s: &str, e: &str| -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+        Err(format!("filename: [{}] couldn't load, {}. {}", filename, s, e).into())
+    };
+    let path = Path::new(filename);
+    let meshes = match path.extension() {
+        None => error("couldn't determine filename extension", ""),
+        Some(ext) => match ext.to_str() {
+            None => error("couldn't parse filename extension", ""),
+            Some(extstr) => match &*extstr.to_lowercase() {
+                "obj" => match load_obj(path) {
+                    Err(e) => error("tobj couldnt load/parse OBJ", &e.to_string()),
+                    Ok(present) => Ok(to_meshes(present.0, present.1)),
+                },
+                "stl" => match OpenOptions::new().read(true).open(path) {
+                    Err(e) => error("STL load failed", &e.to_string()),
+                    Ok(mut file) => match stl_io::read_stl(&mut file) {
+                        Err(e) => error("stl_io couldnt parse STL", &e.to_string()),
+                        Ok(stlio_mesh) => Ok(vec![stlio_mesh.to_simple_mesh()]),
                     },
-                    _ => error("unknown filename extension", ""),
                 },
+                "msh" => match load_msh(path) {
+                    Err(e) => error("couldnt load/parse Gmsh MSH", &e.to_string()),
+                    Ok(meshes) => Ok(meshes),
+                },
+                "ply" => match load_ply(path, face_id) {
+                    Err(e) => error("couldnt load/parse PLY", &e.to_string()),
+                    Ok(meshes) => Ok(meshes),
+                },
+                "gltf" | "glb" => match load_gltf(path) {
+                    Err(e) => error("couldnt load/parse glTF", &e.to_string()),
+                    Ok(meshes) => Ok(meshes),
+                },
+                _ => error("unknown filename extension", ""),
             },
-        };
-        mesh_queue.append(&mut meshes.unwrap());
+        },
+    };
+    let mut meshes = meshes?;
+    for mesh in &mut meshes {
+        mesh.name = filename.to_string();
+    }
+    Ok(meshes)
+}
+
+// A rough upper bound of the bytes loading a file takes, from a quick count of its faces
+pub fn estimate_memory(filename: &str) -> Result<usize, Box<dyn Error>> {
+    let error = |e: &dyn Error| format!("filename: [{}] couldn't load, {}", filename, e);
+    let size = fs::metadata(filename).map_err(|e| error(&e))?.len() as usize;
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_lowercase);
+    let file = || {
+        File::open(filename)
+            .map(BufReader::new)
+            .map_err(|e| error(&e))
+    };
+    let triangles = match extension.as_deref() {
+        Some("obj") => {
+            let mut triangles = 0;
+            for line in file()?.lines() {
+                let line = line.map_err(|e| error(&e))?;
+                let mut tokens = line.split_whitespace();
+                if tokens.next() == Some("f") {
+                    triangles += tokens.count().saturating_sub(2); // Polygons are split into fans
+                }
+            }
+            triangles
+        }
+        Some("msh") => estimate_triangles(file()?).map_err(|e| error(&e))?,
+        Some("ply") => estimate_ply_triangles(file()?).map_err(|e| error(&e))?,
+        // Binary STLs spend 50 bytes per triangle, ASCII ones far more
+        _ => size / 50,
+    };
+    // The loaders keep their own copy of the data until it's converted
+    Ok(triangles * std::mem::size_of::<Triangle>() * 2 + size)
+}
+
+// Refuses a file that would take more than limit bytes to load
+pub fn check_memory(filename: &str, limit: usize) -> Result<(), Box<dyn Error>> {
+    let estimate = estimate_memory(filename)?;
+    if estimate > limit {
+        return Err(format!(
+            "filename: [{}] wasn't loaded, it would take about {:.1}MB, over --max-memory {:.1}MB",
+            filename,
+            estimate as f64 / 1_000_000.0,
+            limit as f64 / 1_000_000.0
+        )
+        .into());
     }
+    Ok(())
+}
+
+// The memory limit in bytes
+pub fn match_max_memory(matches: &ArgMatches) -> Result<Option<usize>, Box<dyn Error>> {
+    match matches.value_of("max memory") {
+        None => Ok(None),
+        Some(megabytes) => Ok(Some((megabytes.parse::<f64>()? * 1_000_000.0) as usize)),
+    }
+}
+
+// Loads a file once it's been checked against --max-memory
+fn load_limited(matches: &ArgMatches, filename: &str) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    if let Some(limit) = match_max_memory(matches)? {
+        check_memory(filename, limit)?;
+    }
+    load_meshes(filename, matches.value_of("face id property"))
+}
+
+// The options that change what preprocessing makes of a file, a cache made with others is stale
+fn preprocess_options(matches: &ArgMatches) -> String {
+    format!(
+        "normalize scale={} face id property={} decimate error={}",
+        matches.value_of("normalize scale").unwrap_or(""),
+        matches.value_of("face id property").unwrap_or(""),
+        matches.value_of("decimate error").unwrap_or("")
+    )
+}
+
+// Loads and preprocesses a file, going through its cache with --cache
+pub fn load_preprocessed(
+    matches: &ArgMatches,
+    filename: &str,
+) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let options = preprocess_options(matches);
+    let cache = matches.is_present("cache");
+    if cache {
+        match read_cache(filename, &options) {
+            Ok(Some(meshes)) => return Ok(meshes),
+            Ok(None) => {}
+            Err(e) => eprintln!("{}", e), // The file itself can still be loaded
+        }
+    }
+    let mut meshes = load_limited(matches, filename)?;
+    match_preprocess(matches, &mut meshes)?;
+    if cache {
+        if let Err(e) = write_cache(filename, &meshes, &options) {
+            eprintln!("{}", e);
+        }
+    }
+    Ok(meshes)
+}
+
+// What's shown while the index-th of count files loads
+fn loading_message(filename: &str, index: usize, count: usize) -> String {
+    if count > 1 {
+        format!("Loading {} ({}/{})", filename, index + 1, count)
+    } else {
+        format!("Loading {}", filename)
+    }
+}
+
+pub fn match_meshes(matches: &ArgMatches) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let mut mesh_queue: Vec<SimpleMesh> = vec![];
+    let filenames: Vec<&str> = match_filename(matches)?.split(' ').collect();
+    for (index, slice) in filenames.iter().enumerate() {
+        // Fill list with file inputs (Splits for spaces -> multiple files)
+        let _loading = LoadingIndicator::new(loading_message(slice, index, filenames.len()));
+        let mut meshes = load_preprocessed(matches, slice)?;
+        for mesh in &mut meshes {
+            mesh.input = index;
+        }
+        mesh_queue.append(&mut meshes);
+    }
+    match_queue_preprocess(matches, &mut mesh_queue);
     Ok(mesh_queue)
 }
 
-pub fn match_turntable(matches: &ArgMatches) -> Result<(f32, f32, f32, f32), Box<dyn Error>> {
+// The preprocessing applied to the meshes of all input files together
+pub fn match_queue_preprocess(matches: &ArgMatches, meshes: &mut [SimpleMesh]) {
+    if matches.is_present("auto orient") {
+        auto_orient(meshes);
+    }
+    if matches.is_present("color by group") {
+        color_by_group(meshes.iter_mut());
+    }
+}
+
+// The preprocessing applied to every input file on its own
+fn match_preprocess(matches: &ArgMatches, meshes: &mut [SimpleMesh]) -> Result<(), Box<dyn Error>> {
+    if let Some(size) = matches.value_of("normalize scale") {
+        normalize_scale(meshes, size.parse()?);
+    }
+    if let Some(error) = matches.value_of("decimate error") {
+        let error: f32 = error.parse()?;
+        if !(error >= 0.0 && error.is_finite()) {
+            return Err(format!(
+                "decimate: the error [{}] isn't a fraction of 0 or more",
+                error
+            )
+            .into());
+        }
+        let count = |meshes: &[SimpleMesh]| meshes.iter().map(|m| m.triangles.len()).sum::<usize>();
+        let before = count(meshes);
+        decimate(meshes, error);
+        let name = meshes.first().map_or("", |mesh| &mesh.name);
+        eprintln!(
+            "decimate: [{}] {} triangles down to {}",
+            name,
+            before,
+            count(meshes)
+        );
+    }
+    Ok(())
+}
+
+// Loads the compare subcommand's two files laid out for comparison
+pub fn match_compare_meshes(
+    matches: &ArgMatches,
+    compare: &ArgMatches,
+) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let load = |index: usize, name: &str| {
+        let filename = compare.value_of(name).unwrap();
+        let _loading = LoadingIndicator::new(loading_message(filename, index, 2));
+        load_preprocessed(matches, filename)
+    };
+    let mut before = load(0, "before")?;
+    let mut after = load(1, "after")?;
+    for mesh in &mut after {
+        mesh.input = 1;
+    }
+    if matches.is_present("auto orient") {
+        auto_orient(&mut before);
+        auto_orient(&mut after);
+    }
+    if matches.is_present("color by group") {
+        color_by_group(before.iter_mut().chain(after.iter_mut()));
+    }
+    if compare.is_present("overlay") {
+        overlay(&mut after);
+    } else {
+        side_by_side(&mut before, &mut after);
+    }
+    before.append(&mut after);
+    Ok(before)
+}
+
+// FNV-1a, used instead of std's DefaultHasher because its output is stable across Rust releases
+fn hash_filename(filename: &str) -> u64 {
+    filename.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+// Turns a filename into euler angles: a gentle tilt, any spin, and a slight roll
+pub fn seeded_rotation(filename: &str) -> (f32, f32, f32) {
+    let hash = hash_filename(filename);
+    let unit = |shift: u32| ((hash >> shift) & 0xFFFF) as f32 / f32::from(u16::MAX); // 0.0..=1.0
+    (
+        (unit(0) - 0.5) * 1.2,
+        unit(16) * 2.0 * std::f32::consts::PI,
+        (unit(32) - 0.5) * 0.6,
+    )
+}
+
+pub fn match_turntable(
+    matches: &ArgMatches,
+    filename: &str,
+) -> Result<(f32, f32, f32, f32), Box<dyn Error>> {
     let mut turntable = (0.0, 0.0, 0.0, 0.0);
+    if matches.is_present("seed rotation") {
+        let seeded = seeded_rotation(filename);
+        turntable.0 = seeded.0;
+        turntable.1 = seeded.1;
+        turntable.2 = seeded.2;
+    }
     if let Some(x) = matches.value_of("x") {
         turntable.0 = x.parse()?;
     }
@@ -141,17 +816,372 @@ pub fn match_turntable(matches: &ArgMatches) -> Result<(f32, f32, f32, f32), Box
         turntable.3 = 1.0; // No speed defined -> 1.0 rad/s
     }
     turntable.1 += std::f32::consts::PI; // All models for some reason are backwards, this fixes that
+    if matches.is_present("isometric") {
+        // The tilt comes after the turn, so it can't be a plain euler angle
+        let (x, y, z) = view_preset("iso")?.euler_angles();
+        turntable = (x, y, z, 0.0);
+    }
     Ok(turntable)
 }
 
+pub fn match_filename<'a>(matches: &'a ArgMatches) -> Result<&'a str, Box<dyn Error>> {
+    match matches.value_of("input filename(s)") {
+        Some(filename) => Ok(filename),
+        None => Err("no input filename(s) given, see --help".into()),
+    }
+}
+
+// Loads an image sampled the way the texture flags ask
+fn load_texture(filename: &str, matches: &ArgMatches) -> Result<Texture, Box<dyn Error>> {
+    let mut texture = Texture::load(filename)?;
+    if matches.value_of("texture filter") == Some("nearest") {
+        texture.filter = TextureFilter::Nearest;
+    }
+    match matches.value_of("texture wrap") {
+        Some("clamp") => texture.wrap = TextureWrap::Clamp,
+        Some("mirror") => texture.wrap = TextureWrap::Mirror,
+        _ => {}
+    }
+    Ok(texture)
+}
+
+fn load_shader(name: &str, matches: &ArgMatches) -> Result<BuiltinShader, Box<dyn Error>> {
+    match (name, matches.value_of("matcap")) {
+        ("default", _) => Ok(BuiltinShader::Default),
+        ("matcap", Some(image)) => Ok(BuiltinShader::Matcap(load_texture(image, matches)?)),
+        ("matcap", None) => Err("the matcap shader needs an image, see --matcap".into()),
+        ("checker", _) => match matches.value_of("checker size") {
+            None => Ok(BuiltinShader::Checker(0.25)),
+            Some(size) => match size.parse::<f32>()? {
+                size if size > 0.0 => Ok(BuiltinShader::Checker(size)),
+                _ => Err("--checker-size must be above 0".into()),
+            },
+        },
+        ("density", _) => Ok(BuiltinShader::Density),
+        _ => Err(format!("unknown shader [{}], expected one of {:?}", name, SHADERS).into()),
+    }
+}
+
+pub fn match_shader(matches: &ArgMatches) -> Result<Option<BuiltinShader>, Box<dyn Error>> {
+    match (matches.value_of("shader"), matches.value_of("matcap")) {
+        (Some(name), _) => Ok(Some(load_shader(name, matches)?)),
+        (None, Some(_)) => Ok(Some(load_shader("matcap", matches)?)),
+        (None, None) => Ok(None),
+    }
+}
+
+// The crease angle in radians when only feature edges should be drawn
+pub fn match_feature_edges(matches: &ArgMatches) -> Result<Option<f32>, Box<dyn Error>> {
+    match matches.value_of("feature edges") {
+        None => Ok(None),
+        Some(angle) => Ok(Some(angle.parse::<f32>()?.to_radians())),
+    }
+}
+
+// Meshes without a --shader-for entry keep their shader, falling back to --shader when drawn
+pub fn match_mesh_shaders(
+    matches: &ArgMatches,
+    meshes: &mut [SimpleMesh],
+) -> Result<(), Box<dyn Error>> {
+    if let Some(values) = matches.values_of("shader for") {
+        let values: Vec<&str> = values.collect();
+        for pair in values.chunks(2) {
+            let mut found = false;
+            for mesh in meshes.iter_mut().filter(|mesh| mesh.name == pair[0]) {
+                mesh.shader = Some(Box::new(load_shader(pair[1], matches)?));
+                found = true;
+            }
+            if !found {
+                return Err(
+                    format!("--shader-for: no input filename [{}] was loaded", pair[0]).into(),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+// The axis is x,y,z in object space, the speed in radians per second
+pub fn match_mesh_spins(
+    matches: &ArgMatches,
+    meshes: &mut [SimpleMesh],
+) -> Result<(), Box<dyn Error>> {
+    if let Some(values) = matches.values_of("spin for") {
+        let values: Vec<&str> = values.collect();
+        for triple in values.chunks(3) {
+            let axis: Vec<&str> = triple[1].split(',').map(str::trim).collect();
+            let axis = match axis.as_slice() {
+                [x, y, z] => Vector3::new(x.parse()?, y.parse()?, z.parse()?),
+                _ => {
+                    return Err(format!(
+                        "--spin-for: axis [{}] isn't an x,y,z triple like 0,1,0",
+                        triple[1]
+                    )
+                    .into())
+                }
+            };
+            let axis = match Unit::try_new(axis, f32::EPSILON) {
+                Some(axis) => axis,
+                None => return Err("--spin-for: the axis can't be 0,0,0".into()),
+            };
+            let speed: f32 = triple[2].trim_start_matches('@').parse()?;
+            let mut found = false;
+            for mesh in meshes.iter_mut().filter(|mesh| mesh.name == triple[0]) {
+                mesh.spin = Some(Spin {
+                    axis,
+                    speed,
+                    angle: 0.0,
+                });
+                found = true;
+            }
+            if !found {
+                return Err(
+                    format!("--spin-for: no input filename [{}] was loaded", triple[0]).into(),
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn match_frame_pacer(matches: &ArgMatches) -> Result<Option<FramePacer>, Box<dyn Error>> {
+    match matches.value_of("max fps vsync") {
+        None => Ok(None),
+        Some(fps) => match fps.parse::<f64>()? {
+            fps if fps > 0.0 => Ok(Some(FramePacer::new(fps))),
+            _ => Err("--max-fps-vsync must be above 0".into()),
+        },
+    }
+}
+
+// Parses an r,g,b triple like 255,128,0
+pub fn parse_color(color: &str) -> Result<(u8, u8, u8), Box<dyn Error>> {
+    let channels: Vec<&str> = color.split(',').map(str::trim).collect();
+    match channels.as_slice() {
+        [r, g, b] => Ok((r.parse()?, g.parse()?, b.parse()?)),
+        _ => Err(format!("color: [{}] isn't an r,g,b triple like 255,128,0", color).into()),
+    }
+}
+
+// The color --transparent-color keys out of --color-png, and how far from it a channel may be
+pub fn match_transparent_color(matches: &ArgMatches) -> Result<Option<(Rgb, u8)>, Box<dyn Error>> {
+    let value = match matches.value_of("transparent color") {
+        None => return Ok(None),
+        Some(value) => value,
+    };
+    match value.rsplitn(2, ',').collect::<Vec<&str>>().as_slice() {
+        // Three channels and a tolerance, or only the channels
+        [tolerance, color] if color.matches(',').count() == 2 => {
+            Ok(Some((parse_color(color)?, tolerance.trim().parse()?)))
+        }
+        _ => Ok(Some((parse_color(value)?, 0))),
+    }
+}
+
+pub fn match_backface_color(
+    context: &mut Context,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(color) = matches.value_of("backface color") {
+        context.backface_color = Some(parse_color(color)?);
+    }
+    Ok(())
+}
+
+pub fn match_wire_depth_colors(
+    context: &mut Context,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(colors) = matches.values_of("wire color by depth") {
+        let colors: Vec<&str> = colors.collect();
+        context.wire_depth_colors = Some([parse_color(colors[0])?, parse_color(colors[1])?]);
+    }
+    Ok(())
+}
+
+pub fn match_depth_tiebreak(context: &mut Context, matches: &ArgMatches) {
+    match matches.value_of("depth tiebreak") {
+        Some("first") => context.depth_tiebreak = DepthTiebreak::First,
+        Some("last") => context.depth_tiebreak = DepthTiebreak::Last,
+        Some("brightest") => context.depth_tiebreak = DepthTiebreak::Brightest,
+        _ => {}
+    }
+    if matches.is_present("coverage blend") {
+        context.depth_tiebreak = DepthTiebreak::Blend;
+        context.coverage_buffer = Some(vec![]);
+    }
+}
+
+// How the vertices are drawn when only they should be, the size and splat flags imply --points
+pub fn match_points(matches: &ArgMatches) -> Result<Option<PointStyle>, Box<dyn Error>> {
+    if !["points", "point size", "splat size", "splat falloff"]
+        .iter()
+        .any(|name| matches.is_present(name))
+    {
+        return Ok(None);
+    }
+    let size = match matches.value_of("point size") {
+        None => 1.0,
+        Some(size) => match size.parse::<f32>()? {
+            size if size > 0.0 => size,
+            _ => return Err("--point-size must be above 0".into()),
+        },
+    };
+    let splat_size = match matches.value_of("splat size") {
+        None => None,
+        Some(size) => Some(size.parse::<f32>()?),
+    };
+    let falloff = match matches.value_of("splat falloff") {
+        Some("circle") => Falloff::Circle,
+        Some("gaussian") => Falloff::Gaussian,
+        _ => Falloff::Square,
+    };
+    Ok(Some(PointStyle {
+        size,
+        splat_size,
+        falloff,
+    }))
+}
+
+pub fn match_fill_chars(context: &mut Context, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    let single = |name: &str| -> Result<Option<char>, Box<dyn Error>> {
+        match matches.value_of(name) {
+            None => Ok(None),
+            Some(value) => {
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(character), None) => Ok(Some(character)),
+                    _ => Err(format!(
+                        "--{}: [{}] isn't a single character",
+                        name.replace(' ', "-"),
+                        value
+                    )
+                    .into()),
+                }
+            }
+        }
+    };
+    if let Some(character) = single("fill char")? {
+        context.fill_char = Some(character);
+    }
+    if let Some(character) = single("edge char")? {
+        context.edge_char = Some(character);
+    }
+    Ok(())
+}
+
+pub fn match_adapt_background(background: &mut Background, matches: &ArgMatches) {
+    if !matches.is_present("adapt background") {
+        return;
+    }
+    // Terminals answer within a few milliseconds, anything slower probably won't ever answer
+    if let Some(color) = query_terminal_background(Duration::from_millis(200)) {
+        background.color = color;
+    }
+    background.adapt = true;
+}
+
+// The hue cycling speed in degrees per second, --rainbow-speed implies --rainbow
+pub fn match_rainbow(matches: &ArgMatches) -> Result<Option<f32>, Box<dyn Error>> {
+    if !matches.is_present("rainbow") && !matches.is_present("rainbow speed") {
+        return Ok(None);
+    }
+    match matches.value_of("rainbow speed") {
+        None => Ok(Some(90.0)),
+        Some(speed) => Ok(Some(speed.parse()?)),
+    }
+}
+
+// The number of frames --webify renders, it has to render at least one
+pub fn match_frame_count(matches: &ArgMatches) -> Result<Option<usize>, Box<dyn Error>> {
+    match matches.value_of("frame count") {
+        None => Ok(None),
+        Some(frames) => match frames.parse()? {
+            0 => Err("webify: needs at least one frame".into()),
+            frames => Ok(Some(frames)),
+        },
+    }
+}
+
+pub fn match_transition(matches: &ArgMatches) -> Result<Option<Transition>, Box<dyn Error>> {
+    let views = match matches.value_of("transition") {
+        None => return Ok(None),
+        Some(views) => views
+            .split(',')
+            .map(|view| view_preset(view.trim()))
+            .collect::<Result<Vec<_>, _>>()?,
+    };
+    let frames = match matches.value_of("transition frames") {
+        None => 60,
+        Some(frames) => frames.parse()?,
+    };
+    if frames == 0 {
+        return Err("transition: needs at least one frame".into());
+    }
+    Ok(Some(Transition { views, frames }))
+}
+
+pub fn match_zoom(camera: &mut Camera, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if let Some(zoom) = matches.value_of("zoom") {
+        camera.zoom = match zoom.parse::<f32>()? {
+            zoom if zoom > 0.0 => zoom,
+            _ => return Err("--zoom must be above 0".into()),
+        };
+    }
+    Ok(())
+}
+
+pub fn match_render_scale(
+    context: &mut Context,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(scale) = matches.value_of("render scale") {
+        context.render_scale = match scale.parse::<f32>()? {
+            scale if scale >= 1.0 => scale,
+            _ => return Err("--render-scale must be at least 1".into()),
+        };
+    }
+    Ok(())
+}
+
 pub fn match_image_mode(matches: &ArgMatches) -> bool {
     matches.is_present("image")
 }
 
+pub fn match_verbose_mode(matches: &ArgMatches) -> bool {
+    matches.is_present("verbose")
+}
+
+pub fn match_stdin_commands(matches: &ArgMatches) -> bool {
+    matches.is_present("stdin commands")
+}
+
+pub fn match_flips(matches: &ArgMatches) -> (bool, bool) {
+    (matches.is_present("flip x"), matches.is_present("flip y"))
+}
+
+pub fn match_bbox_only(matches: &ArgMatches) -> bool {
+    matches.is_present("bbox only")
+}
+
+pub fn match_hide_diagonals(matches: &ArgMatches) -> bool {
+    matches.is_present("hide diagonals")
+}
+
 pub fn match_no_color_mode(matches: &ArgMatches) -> bool {
     matches.is_present("no color")
 }
 
+pub fn match_background(
+    background: &mut Background,
+    matches: &ArgMatches,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(image) = matches.value_of("background image") {
+        background.image = Some(load_texture(image, matches)?);
+    }
+    Ok(())
+}
+
 pub fn match_dimensions(context: &mut Context, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     if let Some(x) = matches.value_of("width") {
         context.width = x.parse()?;
@@ -163,3 +1193,27 @@ pub fn match_dimensions(context: &mut Context, matches: &ArgMatches) -> Result<(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_the_quirks_of_old_exporters() {
+        let source =
+            "\u{feff}# comment\r\no mat#1\t# named with a #\r\nf 1 2 \\\r\n  3 4 # more\r\n";
+        assert_eq!(normalize_obj(source), "\no mat#1\nf 1 2    3 4\n");
+    }
+
+    #[test]
+    fn loads_an_obj_with_the_quirks_of_old_exporters() {
+        let meshes = load_meshes("models/malformed.obj", None).unwrap();
+        assert_eq!(meshes.len(), 1);
+        // The continued line is one quad, split into two triangles
+        let mesh = &meshes[0];
+        assert_eq!(mesh.triangles.len(), 2);
+        // The material is found past the trailing comment on its usemtl line
+        let (r, g, b) = mesh.triangles[0].color;
+        assert!(g > r && g > b, "{:?} isn't green", (r, g, b));
+    }
+}
@@ -0,0 +1,50 @@
+pub mod context;
+pub use context::*;
+
+pub mod geometry;
+pub use geometry::*;
+
+pub mod rasterizer;
+pub use rasterizer::*;
+
+pub mod inputs;
+pub use inputs::*;
+
+pub mod msh;
+pub use msh::*;
+
+pub mod ply;
+pub use ply::*;
+
+pub mod gltf;
+pub use gltf::*;
+
+pub mod decimate;
+pub use decimate::*;
+
+pub mod cache;
+pub use cache::*;
+
+pub mod png;
+pub use png::*;
+
+pub mod texture;
+pub use texture::*;
+
+pub mod timing;
+pub use timing::*;
+
+pub mod background;
+pub use background::*;
+
+pub mod scene;
+pub use scene::*;
+
+pub mod views;
+pub use views::*;
+
+pub mod terminal;
+pub use terminal::*;
+
+pub mod commands;
+pub use commands::*;
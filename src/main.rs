@@ -1,102 +1,361 @@
-use crossterm::{
-    cursor,
-    event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers},
-    ExecutableCommand,
-};
+use crossterm::event::{poll, read, Event, KeyCode, KeyEvent, KeyModifiers};
 use std::error::Error;
 use std::f32;
+use std::fs;
 use std::io::{stdout, Write};
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use nalgebra::Rotation3;
-
-pub mod context;
-pub use context::*;
-
-pub mod geometry;
-pub use geometry::*;
-
-pub mod rasterizer;
-pub use rasterizer::*;
-
-pub mod inputs;
-pub use inputs::*;
+use sloth::*;
 
 fn main() -> Result<(), Box<dyn Error>> {
     let matches = cli_matches(); // Read command line arguments
 
     let fps_cap = 500.0;
     let target_frame_time = Duration::from_secs_f64(1.0 / fps_cap);
+    let mut pacer = match_frame_pacer(&matches)?; // Replaces the fps cap with evenly paced frames
 
-    let mesh_queue: Vec<SimpleMesh> = match_meshes(&matches)?; // A list of meshes to render
-    let mut turntable = match_turntable(&matches)?;
+    let compare = matches.subcommand_matches("compare");
+    let mut mesh_queue: Vec<SimpleMesh> = match compare {
+        Some(compare) => match_compare_meshes(&matches, compare)?,
+        None => match_meshes(&matches)?, // A list of meshes to render
+    };
+    match_mesh_shaders(&matches, &mut mesh_queue)?;
+    match_mesh_spins(&matches, &mut mesh_queue)?;
+    let filename = match compare {
+        Some(compare) => compare.value_of("before").unwrap(),
+        None => match_filename(&matches)?,
+    };
+    let mut scene = Scene::new(mesh_queue, match_turntable(&matches, filename)?);
     let mut stdout = stdout();
     let no_color = match_no_color_mode(&matches);
+    let mut shader = match_shader(&matches)?.unwrap_or(BuiltinShader::Default);
+    scene.feature_edges = match_feature_edges(&matches)?;
+    scene.hide_diagonals = match_hide_diagonals(&matches);
+    scene.flips = match_flips(&matches);
+    scene.bbox_only = match_bbox_only(&matches);
+    scene.points = match_points(&matches)?;
+    scene.rainbow = match_rainbow(&matches)?;
     let mut webify = false;
     let mut webify_frame_count = 0;
     let mut webify_todo_frames = 0;
+    let mut transition = None;
+    let mut verbose = false;
+    let mut depth_png = None;
+    let mut normal_png = None;
+    let mut solid_png = None;
+    let mut wire_png = None;
+    let mut color_png = None;
+    let mut braille_file = None;
+    let mut transparent_color = None;
+    let mut progressive = None;
+    let mut separate_outputs = None;
 
+    let mut terminal_guard = None; // Restores the terminal when main returns, however it does
     let mut context: Context = Context::blank(match_image_mode(&matches)); // The context holds the frame+z buffer, and the width and height
+    match_backface_color(&mut context, &matches)?;
+    match_wire_depth_colors(&mut context, &matches)?;
+    match_depth_tiebreak(&mut context, &matches);
+    match_fill_chars(&mut context, &matches)?;
+    match_zoom(&mut scene.camera, &matches)?;
+    match_adapt_background(&mut scene.background, &matches);
+    match_render_scale(&mut context, &matches)?;
     if context.image {
         if let Some(matches) = matches.subcommand_matches("image") {
-            match_dimensions(&mut context, &matches)?;
-            turntable = match_turntable(matches)?;
-            if let Some(animation_frames) = matches.value_of("frame count") {
-                webify_todo_frames = animation_frames.parse()?;
+            match_dimensions(&mut context, matches)?;
+            match_background(&mut scene.background, matches)?;
+            match_backface_color(&mut context, matches)?;
+            match_wire_depth_colors(&mut context, matches)?;
+            match_depth_tiebreak(&mut context, matches);
+            match_fill_chars(&mut context, matches)?;
+            match_zoom(&mut scene.camera, matches)?;
+            match_adapt_background(&mut scene.background, matches);
+            verbose = match_verbose_mode(matches);
+            depth_png = matches.value_of("depth png");
+            normal_png = matches.value_of("normal png");
+            progressive = matches.value_of("progressive");
+            separate_outputs = matches.value_of("separate outputs");
+            if normal_png.is_some() {
+                context.normal_buffer = Some(vec![]);
+            }
+            solid_png = matches.value_of("solid png");
+            if solid_png.is_some() {
+                context.solid_buffer = Some(vec![]);
+            }
+            color_png = matches.value_of("color png");
+            braille_file = matches.value_of("braille file");
+            transparent_color = match_transparent_color(matches)?;
+            wire_png = matches.value_of("wire png");
+            if wire_png.is_some() {
+                context.wire_buffer = Some(vec![]);
+            }
+            scene.camera.turntable = match_turntable(matches, filename)?;
+            if let Some(image_shader) = match_shader(matches)? {
+                shader = image_shader;
+            }
+            match_mesh_shaders(matches, &mut scene.meshes)?;
+            match_mesh_spins(matches, &mut scene.meshes)?;
+            if let Some(angle) = match_feature_edges(matches)? {
+                scene.feature_edges = Some(angle);
+            }
+            scene.hide_diagonals |= match_hide_diagonals(matches);
+            scene.bbox_only |= match_bbox_only(matches);
+            scene.points = match_points(matches)?.or(scene.points);
+            scene.rainbow = match_rainbow(matches)?.or(scene.rainbow);
+            scene.flips.0 |= match_flips(matches).0;
+            scene.flips.1 |= match_flips(matches).1;
+            if let Some(animation_frames) = match_frame_count(matches)? {
+                webify_todo_frames = animation_frames;
+                webify = true;
+            }
+            transition = match_transition(matches)?;
+            if let Some(transition) = &transition {
+                webify_todo_frames = transition.frames;
                 webify = true;
             }
         }
     } else {
-        crossterm::terminal::enable_raw_mode()?;
-        stdout.execute(cursor::Hide)?;
+        if let Some(compare) = compare {
+            scene.camera.turntable = match_turntable(compare, filename)?;
+            match_mesh_spins(compare, &mut scene.meshes)?;
+            match_backface_color(&mut context, compare)?;
+            match_wire_depth_colors(&mut context, compare)?;
+            match_depth_tiebreak(&mut context, compare);
+            match_fill_chars(&mut context, compare)?;
+            match_zoom(&mut scene.camera, compare)?;
+            match_adapt_background(&mut scene.background, compare);
+            if let Some(compare_shader) = match_shader(compare)? {
+                shader = compare_shader;
+            }
+            if let Some(angle) = match_feature_edges(compare)? {
+                scene.feature_edges = Some(angle);
+            }
+            scene.hide_diagonals |= match_hide_diagonals(compare);
+            scene.bbox_only |= match_bbox_only(compare);
+            scene.points = match_points(compare)?.or(scene.points);
+            scene.rainbow = match_rainbow(compare)?.or(scene.rainbow);
+            scene.flips.0 |= match_flips(compare).0;
+            scene.flips.1 |= match_flips(compare).1;
+        }
+        // Raw mode needs stdin to be the terminal, and would stop commands arriving line by line
+        terminal_guard = Some(TerminalGuard::new(!match_stdin_commands(&matches))?);
     }
+    scene.shader = Box::new(shader);
+    scene.build_edges();
+    // A signal stops the frame being drawn, so quitting doesn't wait for a slow one to finish
+    let cancel = terminal_guard.as_ref().map_or_else(
+        || Arc::new(AtomicBool::new(false)),
+        TerminalGuard::signal_flag,
+    );
+    let commands = if !context.image && match_stdin_commands(&matches) {
+        Some(spawn_stdin_commands())
+    } else {
+        None
+    };
     let size: (u16, u16) = (0, 0); // This is the terminal size, it's used to check when a new context must be made
 
+    if let Some(dir) = separate_outputs {
+        fs::create_dir_all(dir)?;
+        let mut meshes = std::mem::take(&mut scene.meshes);
+        let mut written: Vec<String> = vec![];
+        while !meshes.is_empty() {
+            // The meshes of one input file are next to each other in the queue
+            let (input, name) = (meshes[0].input, meshes[0].name.clone());
+            let end = meshes
+                .iter()
+                .position(|mesh| mesh.input != input)
+                .unwrap_or(meshes.len());
+            let rest = meshes.split_off(end);
+            scene.meshes = meshes;
+            meshes = rest;
+            context.update(size, &scene)?; // Fits this file alone
+            scene.render(&mut context);
+            let stem = Path::new(&name)
+                .file_stem()
+                .map_or(name.clone(), |stem| stem.to_string_lossy().into_owned());
+            // Inputs with the same stem, like a/cube.obj and b/cube.obj, are numbered from the second on
+            let mut output = stem.clone();
+            for number in 2.. {
+                if !written.contains(&output) {
+                    break;
+                }
+                output = format!("{}-{}", stem, number);
+            }
+            fs::write(
+                Path::new(dir).join(format!("{}.txt", output)),
+                context.to_text(),
+            )?;
+            written.push(output);
+        }
+        return Ok(());
+    }
+
     if webify {
         println!("let frames = [");
-        turntable.3 = (2.0 * f32::consts::PI) * (1.0 / webify_todo_frames as f32);
+        // Every frame turns the turntable by its time step, unless the transition points it
+        scene.camera.turntable.3 = if transition.is_some() { 0.0 } else { 1.0 };
     }
+    let webify_step = (2.0 * f32::consts::PI) * (1.0 / webify_todo_frames as f32); // Seconds between web frames
+    let startup_view = (scene.camera.turntable, scene.bbox_only); // What r and Home go back to
+    let mut notice: Option<(String, Instant)> = None; // Shown over the frames for a moment after a key does something
     let mut last_time; // Used in the variable time step
+    let mut render_total = Duration::from_secs(0); // Time spent drawing, without the terminal output
     loop {
         last_time = Instant::now();
-        if !context.image {
-            if poll(target_frame_time - last_time.elapsed())? {
-                if let Event::Key(KeyEvent { code, modifiers }) = read()? {
-                    if code == KeyCode::Char('q')
-                        || (code == KeyCode::Char('c') && (modifiers == KeyModifiers::CONTROL))
-                    {
-                        stdout.execute(cursor::Show)?;
-                        crossterm::terminal::disable_raw_mode()?;
-                        break;
+        let wait = match &pacer {
+            Some(pacer) => pacer.wait(),
+            None => target_frame_time - last_time.elapsed(),
+        };
+        let mut quit = terminal_guard
+            .as_ref()
+            .is_some_and(TerminalGuard::signalled);
+        if !context.image && poll(wait)? {
+            if let Event::Key(KeyEvent { code, modifiers }) = read()? {
+                quit |= code == KeyCode::Char('q')
+                    || (code == KeyCode::Char('c') && (modifiers == KeyModifiers::CONTROL));
+                if code == KeyCode::Char('b') {
+                    scene.bbox_only = !scene.bbox_only;
+                }
+                if code == KeyCode::Char('r') || code == KeyCode::Home {
+                    // Undoes b, and the rotate and speed commands
+                    scene.reset_view(startup_view.0);
+                    scene.bbox_only = startup_view.1;
+                    notice = Some(("view reset".to_string(), Instant::now()));
+                }
+                // c puts a plane through the middle, [ and ] move it along its normal and the arrows turn it
+                let clip_step = fit_extent(&scene.meshes) / 20.0;
+                let turn_step = f32::consts::PI / 24.0;
+                let clip = &mut context.clip_plane;
+                match (code, clip.as_mut()) {
+                    (KeyCode::Char('c'), _) if modifiers != KeyModifiers::CONTROL => {
+                        *clip = match clip {
+                            Some(_) => None,
+                            None => Some(ClipPlane {
+                                yaw: 0.0,
+                                pitch: 0.0,
+                                offset: 0.0,
+                            }),
+                        }
+                    }
+                    (KeyCode::Char('['), Some(plane)) => plane.offset -= clip_step,
+                    (KeyCode::Char(']'), Some(plane)) => plane.offset += clip_step,
+                    (KeyCode::Left, Some(plane)) => plane.yaw -= turn_step,
+                    (KeyCode::Right, Some(plane)) => plane.yaw += turn_step,
+                    (KeyCode::Up, Some(plane)) => {
+                        plane.pitch = (plane.pitch + turn_step).min(f32::consts::FRAC_PI_2)
+                    }
+                    (KeyCode::Down, Some(plane)) => {
+                        plane.pitch = (plane.pitch - turn_step).max(-f32::consts::FRAC_PI_2)
                     }
+                    _ => {}
                 }
             }
         }
+        for line in commands.iter().flat_map(|commands| commands.try_iter()) {
+            match Command::parse(&line) {
+                Err(e) => eprintln!("{}", e),
+                Ok(Command::Rotate(x, y, z)) => {
+                    scene.camera.turntable.0 = x;
+                    scene.camera.turntable.1 = y + f32::consts::PI; // The same backwards fix as match_turntable
+                    scene.camera.turntable.2 = z;
+                }
+                Ok(Command::Speed(speed)) => scene.camera.turntable.3 = speed,
+                Ok(Command::Load(filenames)) => {
+                    // Prepared like the files given on the command line, a file that fails is skipped
+                    let mut meshes = vec![];
+                    for (index, filename) in filenames.split(' ').enumerate() {
+                        match load_preprocessed(&matches, filename) {
+                            Err(e) => eprintln!("{}", e),
+                            Ok(mut loaded) => {
+                                for mesh in &mut loaded {
+                                    mesh.input = index;
+                                }
+                                meshes.append(&mut loaded);
+                            }
+                        }
+                    }
+                    match_queue_preprocess(&matches, &mut meshes);
+                    let mut prepared = match_mesh_shaders(&matches, &mut meshes)
+                        .and_then(|_| match_mesh_spins(&matches, &mut meshes));
+                    if let Some(compare) = compare {
+                        prepared = prepared.and_then(|_| match_mesh_spins(compare, &mut meshes));
+                    }
+                    if let Err(e) = prepared {
+                        eprintln!("{}", e);
+                    }
+                    scene.load(meshes);
+                }
+                Ok(Command::Screenshot(filename)) => {
+                    if let Err(e) = fs::write(&filename, context.to_text()) {
+                        eprintln!("screenshot: [{}] couldn't be written, {}", filename, e);
+                    }
+                }
+                Ok(Command::Quit) => quit = true,
+            }
+        }
+        if quit {
+            drop(terminal_guard.take());
+            break;
+        }
 
-        let rot =
-            Rotation3::from_euler_angles(turntable.0, turntable.1, turntable.2).to_homogeneous();
-        context.update(size, &mesh_queue)?; // This checks for if there needs to be a context update
-        context.clear(); // This clears the z and frame buffer
-        for mesh in &mesh_queue {
-            // Render all in mesh queue
-            draw_mesh(&mut context, &mesh, rot, default_shader); // Draw all meshes
+        if let Some(transition) = &transition {
+            scene.look_from(transition.orientation(webify_frame_count));
+        }
+        if let Some(path) = braille_file {
+            // Every braille character holds 2 by 4 pixels, so it's drawn at four times the size first
+            let (columns, rows) = (context.width, context.height);
+            context.width = columns * 4;
+            context.height = rows * 4;
+            context.update(size, &scene)?;
+            scene.render(&mut context);
+            let text = context.braille_text(columns, rows);
+            context.width = columns;
+            context.height = rows;
+            fs::write(path, text)
+                .map_err(|e| format!("braille: [{}] couldn't be written, {}", path, e))?;
         }
+        let render_time = Instant::now();
+        let finished = if let Some(path) = progressive {
+            // Each pass halves the size of the one before, ending with the full size frame
+            scene.render_progressive(&mut context, 4, &cancel, |pass| {
+                Ok(fs::write(path, pass.to_text())?)
+            })?
+        } else {
+            context.update(size, &scene)?; // This checks for if there needs to be a context update
+            scene.render_cancellable(&mut context, &cancel)
+        };
+        if !finished {
+            continue; // The partly drawn frame isn't shown, the next turn of the loop quits
+        }
+        let rendered = render_time.elapsed();
+        render_total += rendered;
 
         if webify {
             println!("`");
         }
 
-        context.flush(!no_color, webify)?; // This prints all framebuffer info
+        context.flush(!no_color, webify, &scene.background)?; // This prints all framebuffer info
+        if let Some(plane) = context.clip_plane {
+            show_notice(&plane.to_string())?; // Kept up for as long as the plane cuts
+        }
+        if let Some((text, shown)) = &notice {
+            if shown.elapsed() < Duration::from_millis(1500) {
+                show_notice(text)?;
+            } else {
+                notice = None;
+            }
+        }
         stdout.flush()?;
-        let dt = Instant::now().duration_since(last_time).as_nanos() as f32 / 1_000_000_000.0;
-        turntable.1 += if webify {
-            turntable.3
-        } else {
-            (turntable.3 * dt) as f32
+        let dt = match &mut pacer {
+            Some(pacer) => pacer.record(rendered, last_time.elapsed()),
+            None => Instant::now().duration_since(last_time).as_nanos() as f32 / 1_000_000_000.0,
         };
+        scene.advance(if webify { webify_step } else { dt });
 
         if webify {
-            if turntable.1 > 9.42477 || webify_todo_frames - 1 == webify_frame_count {
+            if scene.camera.turntable.1 > 9.42477 || webify_todo_frames - 1 == webify_frame_count {
                 println!("`];");
                 break;
             } else {
@@ -110,5 +369,44 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     }
 
+    if let Some(filename) = depth_png {
+        write_depth_png(filename, &context.depth_rows(scene.flips.0, scene.flips.1))?;
+    }
+    if let Some(filename) = normal_png {
+        write_normal_png(filename, &context.normal_rows(scene.flips.0, scene.flips.1))?;
+    }
+
+    if let Some(filename) = solid_png {
+        let rows = context.layer_rows(&context.solid_buffer, scene.flips.0, scene.flips.1);
+        write_color_png(filename, &rows)?;
+    }
+    if let Some(filename) = wire_png {
+        let rows = context.layer_rows(&context.wire_buffer, scene.flips.0, scene.flips.1);
+        write_color_png(filename, &rows)?;
+    }
+    if let Some(filename) = color_png {
+        let mut rows = context.color_rows(&scene.background);
+        if let Some((key, tolerance)) = transparent_color {
+            chroma_key(&mut rows, key, tolerance);
+        }
+        write_color_png(filename, &rows)?;
+    }
+
+    if verbose {
+        // stderr keeps the statistics out of piped renders
+        let stats = &context.stats;
+        let seconds = render_total.as_secs_f64();
+        eprintln!(
+            "triangles: {} drawn, {} culled",
+            stats.triangles_drawn, stats.triangles_culled
+        );
+        eprintln!(
+            "fill rate: {} fragments, {:.0} fragments/s",
+            stats.fragments,
+            stats.fragments as f64 / seconds
+        );
+        eprintln!("render time: {:.3}ms", seconds * 1000.0);
+    }
+
     Ok(())
 }
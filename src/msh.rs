@@ -0,0 +1,365 @@
+use crate::geometry::{color_by_group, SimpleMesh, Triangle};
+use nalgebra::Vector4;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+// The whitespace separated values between $Name and $EndName
+struct Section<'a> {
+    name: &'a str,
+    tokens: SplitWhitespace<'a>,
+}
+
+impl<'a> Section<'a> {
+    fn find(text: &'a str, name: &'a str) -> Option<Section<'a>> {
+        let (begin, end) = (format!("${}", name), format!("$End{}", name));
+        let mut offset = 0;
+        let mut start = None;
+        for line in text.split_inclusive('\n') {
+            if start.is_none() && line.trim() == begin {
+                start = Some(offset + line.len());
+            } else if let (Some(start), true) = (start, line.trim() == end) {
+                return Some(Section {
+                    name,
+                    tokens: text[start..offset].split_whitespace(),
+                });
+            }
+            offset += line.len();
+        }
+        start.map(|start| Section {
+            name,
+            tokens: text[start..].split_whitespace(),
+        })
+    }
+
+    fn next<T: FromStr>(&mut self) -> Result<T, String> {
+        match self.tokens.next() {
+            None => Err(format!("the ${} section is truncated", self.name)),
+            Some(token) => token.parse().map_err(|_| {
+                format!(
+                    "the ${} section has a malformed value [{}]",
+                    self.name, token
+                )
+            }),
+        }
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), String> {
+        for _ in 0..count {
+            self.next::<String>()?;
+        }
+        Ok(())
+    }
+}
+
+enum Element {
+    Skipped, // Points and lines have no surface to draw
+    Triangle,
+    Quadrangle,
+    Tetrahedron, // Only the faces on the outside of the volume are drawn
+}
+
+// What an element type is drawn as, and how many nodes it lists (higher order elements list their corners first)
+fn element(kind: usize) -> Result<(Element, usize), String> {
+    match kind {
+        15 => Ok((Element::Skipped, 1)),
+        1 => Ok((Element::Skipped, 2)),
+        8 => Ok((Element::Skipped, 3)),
+        2 => Ok((Element::Triangle, 3)),
+        9 => Ok((Element::Triangle, 6)),
+        3 => Ok((Element::Quadrangle, 4)),
+        16 => Ok((Element::Quadrangle, 8)),
+        10 => Ok((Element::Quadrangle, 9)),
+        4 => Ok((Element::Tetrahedron, 4)),
+        11 => Ok((Element::Tetrahedron, 10)),
+        _ => Err(format!(
+            "element type {} isn't supported, only points, lines, triangles, quadrangles and tetrahedra are",
+            kind
+        )),
+    }
+}
+
+// How many triangles the elements of an MSH file make at most, from its $Elements section alone so
+// a file can be sized up before it's parsed. Every face of a tetrahedron counts, inner ones too
+pub fn estimate_triangles(reader: impl BufRead) -> io::Result<usize> {
+    let weight = |kind: &str| match kind.parse().ok().and_then(|kind| element(kind).ok()) {
+        Some((Element::Triangle, _)) => 1,
+        Some((Element::Quadrangle, _)) => 2,
+        Some((Element::Tetrahedron, _)) => 4,
+        _ => 0,
+    };
+    let mut section = String::new();
+    let mut counts = false; // The first line of a section, which $Elements starts with its counts
+    let mut version4 = false;
+    let mut block = (0, 0); // The elements left in a version 4 block, and the triangles each makes
+    let mut triangles = 0;
+    for line in reader.lines() {
+        let line = line?;
+        let mut tokens = line.split_whitespace();
+        if line.starts_with('$') {
+            section = line.trim().to_string();
+            counts = true;
+            continue;
+        }
+        match section.as_str() {
+            "$MeshFormat" => version4 = line.trim_start().starts_with('4'),
+            "$Elements" if counts => {}
+            // Version 4 lists elements in blocks of one type, after a line with that type and their count
+            "$Elements" if version4 && block.0 == 0 => {
+                let kind = tokens.nth(2).map_or(0, weight);
+                block = (
+                    tokens.next().and_then(|n| n.parse().ok()).unwrap_or(0),
+                    kind,
+                );
+            }
+            "$Elements" if version4 => {
+                block.0 -= 1;
+                triangles += block.1;
+            }
+            "$Elements" => triangles += tokens.nth(1).map_or(0, weight),
+            _ => {}
+        }
+        counts = false;
+    }
+    Ok(triangles)
+}
+
+// Everything read from the file, faces and tetrahedra are node tags with their physical group
+#[derive(Default)]
+struct Msh {
+    nodes: HashMap<usize, Vector4<f32>>,
+    faces: Vec<(usize, Vec<usize>)>,
+    tetrahedra: Vec<(usize, [usize; 4])>,
+}
+
+impl Msh {
+    fn add(&mut self, kind: usize, group: usize, nodes: &[usize]) -> Result<(), String> {
+        match element(kind)?.0 {
+            Element::Skipped => {}
+            Element::Triangle => self.faces.push((group, nodes[..3].to_vec())),
+            Element::Quadrangle => self.faces.push((group, nodes[..4].to_vec())),
+            Element::Tetrahedron => self
+                .tetrahedra
+                .push((group, [nodes[0], nodes[1], nodes[2], nodes[3]])),
+        }
+        Ok(())
+    }
+
+    fn parse_v2(&mut self, text: &str) -> Result<(), String> {
+        let mut nodes = Section::find(text, "Nodes").ok_or("there is no $Nodes section")?;
+        for _ in 0..nodes.next::<usize>()? {
+            let tag = nodes.next()?;
+            let position = Vector4::new(nodes.next()?, nodes.next()?, nodes.next()?, 1.0);
+            self.nodes.insert(tag, position);
+        }
+        let mut elements =
+            Section::find(text, "Elements").ok_or("there is no $Elements section")?;
+        for _ in 0..elements.next::<usize>()? {
+            elements.skip(1)?; // The element's own tag
+            let kind = elements.next()?;
+            let tags: usize = elements.next()?;
+            // The first tag is the physical group, 0 when it has none
+            let group = if tags > 0 { elements.next()? } else { 0 };
+            elements.skip(tags.saturating_sub(1))?;
+            let corners = (0..element(kind)?.1)
+                .map(|_| elements.next())
+                .collect::<Result<Vec<usize>, String>>()?;
+            self.add(kind, group, &corners)?;
+        }
+        Ok(())
+    }
+
+    fn parse_v4(&mut self, text: &str) -> Result<(), String> {
+        // Version 4 keeps the physical groups on the entities (points, curves, surfaces and volumes)
+        let mut groups: HashMap<(usize, usize), usize> = HashMap::new();
+        if let Some(mut entities) = Section::find(text, "Entities") {
+            let counts: Vec<usize> = (0..4)
+                .map(|_| entities.next())
+                .collect::<Result<_, String>>()?;
+            for (dimension, &count) in counts.iter().enumerate() {
+                for _ in 0..count {
+                    let tag = entities.next()?;
+                    entities.skip(if dimension == 0 { 3 } else { 6 })?; // The position or the bounding box
+                    let physicals: usize = entities.next()?;
+                    if physicals > 0 {
+                        groups.insert((dimension, tag), entities.next()?);
+                    }
+                    entities.skip(physicals.saturating_sub(1))?;
+                    if dimension > 0 {
+                        let bounds = entities.next()?;
+                        entities.skip(bounds)?;
+                    }
+                }
+            }
+        }
+
+        let mut nodes = Section::find(text, "Nodes").ok_or("there is no $Nodes section")?;
+        let blocks = nodes.next()?;
+        nodes.skip(3)?;
+        for _ in 0..blocks {
+            let dimension: usize = nodes.next()?;
+            nodes.skip(1)?;
+            let parametric: usize = nodes.next()?;
+            let count = nodes.next()?;
+            let tags = (0..count)
+                .map(|_| nodes.next())
+                .collect::<Result<Vec<usize>, String>>()?;
+            for tag in tags {
+                let position = Vector4::new(nodes.next()?, nodes.next()?, nodes.next()?, 1.0);
+                nodes.skip(parametric * dimension)?;
+                self.nodes.insert(tag, position);
+            }
+        }
+
+        let mut elements =
+            Section::find(text, "Elements").ok_or("there is no $Elements section")?;
+        let blocks = elements.next()?;
+        elements.skip(3)?;
+        for _ in 0..blocks {
+            let entity = (elements.next()?, elements.next()?);
+            let group = groups.get(&entity).copied().unwrap_or(0);
+            let kind = elements.next()?;
+            for _ in 0..elements.next::<usize>()? {
+                elements.skip(1)?;
+                let corners = (0..element(kind)?.1)
+                    .map(|_| elements.next())
+                    .collect::<Result<Vec<usize>, String>>()?;
+                self.add(kind, group, &corners)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The tetrahedra's faces that no other tetrahedron or surface element shares, wound to face outwards
+    fn boundary_faces(&self) -> Result<Vec<(usize, Vec<usize>)>, String> {
+        let key = |face: &[usize]| {
+            let mut key = face.to_vec();
+            key.sort_unstable();
+            key
+        };
+        let surfaces: HashSet<Vec<usize>> = self.faces.iter().map(|(_, face)| key(face)).collect();
+        // Faces keep the order they were found in, and how many tetrahedra share them
+        let mut faces: Vec<(usize, usize, Vec<usize>)> = vec![];
+        let mut found: HashMap<Vec<usize>, usize> = HashMap::new();
+        for (group, tetrahedron) in &self.tetrahedra {
+            for opposite in 0..4 {
+                let mut face: Vec<usize> = (0..4)
+                    .filter(|&i| i != opposite)
+                    .map(|i| tetrahedron[i])
+                    .collect();
+                let (a, b, c) = (
+                    self.node(face[0])?,
+                    self.node(face[1])?,
+                    self.node(face[2])?,
+                );
+                let d = self.node(tetrahedron[opposite])?;
+                let normal = (b - a).xyz().cross(&(c - a).xyz());
+                if normal.dot(&(d - a).xyz()) > 0.0 {
+                    face.swap(1, 2);
+                }
+                let key = key(&face);
+                if surfaces.contains(&key) {
+                    continue;
+                }
+                let index = *found.entry(key).or_insert_with(|| {
+                    faces.push((0, *group, face));
+                    faces.len() - 1
+                });
+                faces[index].0 += 1;
+            }
+        }
+        Ok(faces
+            .into_iter()
+            .filter(|(count, _, _)| *count == 1)
+            .map(|(_, group, face)| (group, face))
+            .collect())
+    }
+
+    fn node(&self, tag: usize) -> Result<Vector4<f32>, String> {
+        match self.nodes.get(&tag) {
+            None => Err(format!(
+                "an element uses node {}, which isn't in $Nodes",
+                tag
+            )),
+            Some(position) => Ok(*position),
+        }
+    }
+}
+
+// Loads the triangles, quadrangles and outer tetrahedron faces of an ASCII Gmsh
+// mesh (version 2.2 or 4.1), with one mesh per physical group
+pub fn load_msh(path: &Path) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    let mut format = Section::find(&text, "MeshFormat").ok_or("there is no $MeshFormat section")?;
+    let version: String = format.next()?;
+    if format.next::<usize>()? != 0 {
+        return Err("binary MSH files aren't supported, save them as ASCII".into());
+    }
+    let mut msh = Msh::default();
+    // 4.0 lays out $Entities and $Nodes differently from 4.1, so only the exact versions are read
+    match version.as_str() {
+        "2.2" => msh.parse_v2(&text)?,
+        "4.1" => msh.parse_v4(&text)?,
+        _ => {
+            return Err(format!(
+                "MSH version {} isn't supported, only 2.2 and 4.1 are",
+                version
+            )
+            .into())
+        }
+    }
+
+    let mut faces = msh.boundary_faces()?;
+    faces.splice(0..0, msh.faces.drain(..));
+    // Groups keep the order they first appear in, so their colors are the same every time
+    let mut groups: Vec<(usize, Vec<Triangle>)> = vec![];
+    for (group, face) in faces {
+        let index = match groups.iter().position(|(g, _)| *g == group) {
+            Some(index) => index,
+            None => {
+                groups.push((group, vec![]));
+                groups.len() - 1
+            }
+        };
+        // Quadrangles are split along their first diagonal
+        for i in 1..face.len() - 1 {
+            groups[index].1.push(Triangle {
+                color: (0xFF, 0xFF, 0xFF),
+                v1: msh.node(face[0])?,
+                v2: msh.node(face[i])?,
+                v3: msh.node(face[i + 1])?,
+                diagonals: [i > 1, false, i + 2 < face.len()],
+                uvs: None,
+            });
+        }
+    }
+    if groups.is_empty() {
+        return Err("there are no triangles, quadrangles or tetrahedra".into());
+    }
+    let mut meshes: Vec<SimpleMesh> = groups
+        .into_iter()
+        .map(|(_, triangles)| SimpleMesh::new(String::new(), triangles, vec![]))
+        .collect();
+    color_by_group(&mut meshes);
+    Ok(meshes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_count_only_elements_by_type() {
+        let v2 = "$MeshFormat\n2.2 0 8\n$EndMeshFormat\n$Nodes\n1\n1 0 0 0\n$EndNodes\n\
+                  $Elements\n4\n1 15 2 0 1 1\n2 2 2 0 1 1 2 3\n3 3 2 0 1 1 2 3 4\n\
+                  4 4 2 0 1 1 2 3 4\n$EndElements\n";
+        assert_eq!(estimate_triangles(v2.as_bytes()).unwrap(), 1 + 2 + 4);
+        let v4 = "$MeshFormat\n4.1 0 8\n$EndMeshFormat\n$Elements\n4 4 1 4\n\
+                  2 1 2 2\n1 1 2 3\n2 1 3 4\n2 1 3 1\n3 1 2 3 4\n3 1 4 1\n4 1 2 3 4\n\
+                  0 1 15 0\n$EndElements\n";
+        assert_eq!(estimate_triangles(v4.as_bytes()).unwrap(), 2 + 2 + 4);
+    }
+}
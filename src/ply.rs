@@ -0,0 +1,404 @@
+use crate::geometry::{color_by_group, SimpleMesh, Triangle};
+use nalgebra::Vector4;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::str::SplitWhitespace;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Clone, Copy)]
+enum Scalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl Scalar {
+    fn parse(name: &str) -> Result<Scalar, String> {
+        match name {
+            "char" | "int8" => Ok(Scalar::I8),
+            "uchar" | "uint8" => Ok(Scalar::U8),
+            "short" | "int16" => Ok(Scalar::I16),
+            "ushort" | "uint16" => Ok(Scalar::U16),
+            "int" | "int32" => Ok(Scalar::I32),
+            "uint" | "uint32" => Ok(Scalar::U32),
+            "float" | "float32" => Ok(Scalar::F32),
+            "double" | "float64" => Ok(Scalar::F64),
+            _ => Err(format!("property type {} isn't one PLY has", name)),
+        }
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+}
+
+struct Property {
+    name: String,
+    count: Option<Scalar>, // The type of a list's length, None for a single value
+    value: Scalar,
+}
+
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn property(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| names.contains(&property.name.as_str()))
+    }
+}
+
+struct Header {
+    format: Format,
+    elements: Vec<Element>,
+    length: usize, // The bytes up to and including the end_header line
+}
+
+impl Header {
+    fn parse(bytes: &[u8]) -> Result<Header, String> {
+        if !bytes.starts_with(b"ply") {
+            return Err("not a PLY file, it doesn't start with ply".into());
+        }
+        let mut format = None;
+        let mut elements: Vec<Element> = vec![];
+        let mut length = 0;
+        for line in bytes.split_inclusive(|&byte| byte == b'\n') {
+            length += line.len();
+            let line = String::from_utf8_lossy(line);
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            match tokens.as_slice() {
+                ["end_header"] => {
+                    return Ok(Header {
+                        format: format.ok_or("the header has no format line")?,
+                        elements,
+                        length,
+                    })
+                }
+                ["format", kind, _] => {
+                    format = Some(match *kind {
+                        "ascii" => Format::Ascii,
+                        "binary_little_endian" => Format::BinaryLittleEndian,
+                        "binary_big_endian" => Format::BinaryBigEndian,
+                        _ => return Err(format!("format {} isn't one PLY has", kind)),
+                    })
+                }
+                ["element", name, count] => elements.push(Element {
+                    name: name.to_string(),
+                    count: count
+                        .parse()
+                        .map_err(|_| format!("element {} has a malformed count", name))?,
+                    properties: vec![],
+                }),
+                ["property", "list", count, value, name] => elements
+                    .last_mut()
+                    .ok_or("a property comes before any element")?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        count: Some(Scalar::parse(count)?),
+                        value: Scalar::parse(value)?,
+                    }),
+                ["property", value, name] => elements
+                    .last_mut()
+                    .ok_or("a property comes before any element")?
+                    .properties
+                    .push(Property {
+                        name: name.to_string(),
+                        count: None,
+                        value: Scalar::parse(value)?,
+                    }),
+                _ => {} // ply, comment and obj_info lines
+            }
+        }
+        Err("the header has no end_header line".into())
+    }
+}
+
+// The values after the header, read one at a time in whichever format they're stored
+enum Body<'a> {
+    Ascii(SplitWhitespace<'a>),
+    Binary { bytes: &'a [u8], big_endian: bool },
+}
+
+impl<'a> Body<'a> {
+    fn next(&mut self, scalar: Scalar) -> Result<f64, String> {
+        match self {
+            Body::Ascii(tokens) => {
+                let token = tokens.next().ok_or("the file is truncated")?;
+                token
+                    .parse()
+                    .map_err(|_| format!("the file has a malformed value [{}]", token))
+            }
+            Body::Binary { bytes, big_endian } => {
+                if bytes.len() < scalar.size() {
+                    return Err("the file is truncated".into());
+                }
+                let (value, rest) = bytes.split_at(scalar.size());
+                *bytes = rest;
+                let mut word = [0; 8];
+                word[..value.len()].copy_from_slice(value);
+                if *big_endian {
+                    word[..value.len()].reverse();
+                }
+                Ok(match scalar {
+                    Scalar::I8 => f64::from(word[0] as i8),
+                    Scalar::U8 => f64::from(word[0]),
+                    Scalar::I16 => f64::from(i16::from_le_bytes([word[0], word[1]])),
+                    Scalar::U16 => f64::from(u16::from_le_bytes([word[0], word[1]])),
+                    Scalar::I32 => {
+                        f64::from(i32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    }
+                    Scalar::U32 => {
+                        f64::from(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    }
+                    Scalar::F32 => {
+                        f64::from(f32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    }
+                    Scalar::F64 => f64::from_le_bytes(word),
+                })
+            }
+        }
+    }
+
+    // Reads one row of an element, every property's values into its own entry of row
+    fn row(&mut self, element: &Element, row: &mut Vec<Vec<f64>>) -> Result<(), String> {
+        row.resize(element.properties.len(), vec![]);
+        for (property, values) in element.properties.iter().zip(row.iter_mut()) {
+            values.clear();
+            let count = match property.count {
+                None => 1,
+                Some(count) => self.next(count)? as usize,
+            };
+            for _ in 0..count {
+                values.push(self.next(property.value)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+// How many faces a PLY file has, from its header alone so a file can be sized up before it's
+// parsed. Polygons make more than one triangle, but the faces of scans are nearly always triangles
+pub fn estimate_ply_triangles(reader: impl BufRead) -> io::Result<usize> {
+    for line in reader.split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["element", "face", count] => return Ok(count.parse().unwrap_or(0)),
+            ["end_header"] => break,
+            _ => {}
+        }
+    }
+    Ok(0)
+}
+
+// Loads the faces of an ASCII or binary PLY file, or its vertices when it has no faces. With
+// face_id set, faces are split into one mesh per value of that integer face property
+pub fn load_ply(path: &Path, face_id: Option<&str>) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let header = Header::parse(&bytes)?;
+    let body = &bytes[header.length..];
+    let mut body = match header.format {
+        Format::Ascii => Body::Ascii(std::str::from_utf8(body)?.split_whitespace()),
+        format => Body::Binary {
+            bytes: body,
+            big_endian: format == Format::BinaryBigEndian,
+        },
+    };
+
+    let mut vertices: Vec<Vector4<f32>> = vec![];
+    let mut faces: Vec<(i64, Vec<usize>)> = vec![]; // The face's id, 0 without face_id, and its vertices
+    let mut row = vec![];
+    for element in &header.elements {
+        match element.name.as_str() {
+            "vertex" => {
+                let (x, y, z) = match (
+                    element.property(&["x"]),
+                    element.property(&["y"]),
+                    element.property(&["z"]),
+                ) {
+                    (Some(x), Some(y), Some(z)) => (x, y, z),
+                    _ => return Err("the vertices don't all have x, y and z".into()),
+                };
+                for _ in 0..element.count {
+                    body.row(element, &mut row)?;
+                    let value = |index: usize| row[index].first().copied().unwrap_or(0.0) as f32;
+                    vertices.push(Vector4::new(value(x), value(y), value(z), 1.0));
+                }
+            }
+            "face" => {
+                let indices = element
+                    .property(&["vertex_indices", "vertex_index"])
+                    .ok_or("the faces have no vertex_indices")?;
+                let id = match face_id {
+                    None => None,
+                    Some(name) => Some(element.property(&[name]).ok_or_else(|| {
+                        let names: Vec<&str> = element
+                            .properties
+                            .iter()
+                            .map(|property| property.name.as_str())
+                            .collect();
+                        format!(
+                            "the faces have no {} property, only {}",
+                            name,
+                            names.join(", ")
+                        )
+                    })?),
+                };
+                for _ in 0..element.count {
+                    body.row(element, &mut row)?;
+                    let id = id.map_or(0, |id| row[id].first().copied().unwrap_or(0.0) as i64);
+                    let face = row[indices].iter().map(|&index| index as usize).collect();
+                    faces.push((id, face));
+                }
+            }
+            // Edges, materials and anything else are read past
+            _ => {
+                for _ in 0..element.count {
+                    body.row(element, &mut row)?;
+                }
+            }
+        }
+    }
+
+    if faces.is_empty() {
+        return Ok(vec![SimpleMesh::new(String::new(), vec![], vertices)]);
+    }
+    let vertex = |index: usize| match vertices.get(index) {
+        None => Err(format!(
+            "a face uses vertex {}, there are only {}",
+            index,
+            vertices.len()
+        )),
+        Some(position) => Ok(*position),
+    };
+    // Segments keep the order they first appear in, so their colors are the same every time
+    let mut segments: Vec<(i64, Vec<Triangle>)> = vec![];
+    for (id, face) in faces {
+        let index = match segments.iter().position(|(i, _)| *i == id) {
+            Some(index) => index,
+            None => {
+                segments.push((id, vec![]));
+                segments.len() - 1
+            }
+        };
+        // Polygons are split into fans
+        for i in 1..face.len().saturating_sub(1) {
+            segments[index].1.push(Triangle {
+                color: (0xFF, 0xFF, 0xFF),
+                v1: vertex(face[0])?,
+                v2: vertex(face[i])?,
+                v3: vertex(face[i + 1])?,
+                diagonals: [i > 1, false, i + 2 < face.len()],
+                uvs: None,
+            });
+        }
+    }
+    let mut meshes: Vec<SimpleMesh> = segments
+        .into_iter()
+        .map(|(_, triangles)| SimpleMesh::new(String::new(), triangles, vec![]))
+        .collect();
+    if face_id.is_some() {
+        color_by_group(&mut meshes);
+    }
+    Ok(meshes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const HEADER: &str = "element vertex 4\nproperty float x\nproperty float y\nproperty float z\n\
+                          element face 2\nproperty list uchar int vertex_indices\nproperty int segment\n\
+                          end_header\n";
+
+    // Goes through a file of its own, the tests run at the same time
+    fn load(bytes: &[u8], face_id: Option<&str>) -> Result<Vec<SimpleMesh>, Box<dyn Error>> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "sloth-test-{}-{}.ply",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, bytes)?;
+        let meshes = load_ply(&path, face_id);
+        fs::remove_file(&path)?;
+        meshes
+    }
+
+    #[test]
+    fn ascii_and_binary_files_load_the_same() {
+        let ascii = format!(
+            "ply\nformat ascii 1.0\ncomment a quad and a triangle\n{}\
+             0 0 0\n1 0 0\n1 1 0\n0 1 0\n4 0 1 2 3 7\n3 0 2 3 9\n",
+            HEADER
+        );
+        let mut binary = format!("ply\nformat binary_big_endian 1.0\n{}", HEADER).into_bytes();
+        for value in [
+            0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0, 0.0,
+        ] {
+            binary.extend_from_slice(&value.to_be_bytes());
+        }
+        for face in [&[0i32, 1, 2, 3, 7][..], &[0, 2, 3, 9]] {
+            binary.push(face.len() as u8 - 1);
+            for value in face {
+                binary.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        for bytes in [ascii.as_bytes(), &binary] {
+            let meshes = load(bytes, None).unwrap();
+            assert_eq!(meshes.len(), 1);
+            assert_eq!(meshes[0].triangles.len(), 3);
+            assert_eq!(meshes[0].triangles[1].v3, Vector4::new(0.0, 1.0, 0.0, 1.0));
+
+            let segments = load(bytes, Some("segment")).unwrap();
+            assert_eq!(segments.len(), 2);
+            assert_eq!(segments[0].triangles.len(), 2);
+            assert_eq!(segments[1].triangles.len(), 1);
+            assert_ne!(
+                segments[0].triangles[0].color,
+                segments[1].triangles[0].color
+            );
+        }
+    }
+
+    #[test]
+    fn missing_properties_and_vertices_are_errors() {
+        let ascii = format!(
+            "ply\nformat ascii 1.0\n{}0 0 0\n1 0 0\n1 1 0\n0 1 0\n3 0 1 4 1\n3 0 1 2 1\n",
+            HEADER
+        );
+        assert!(load(ascii.as_bytes(), Some("material")).is_err());
+        assert!(load(ascii.as_bytes(), None).is_err()); // Vertex 4 doesn't exist
+        assert_eq!(
+            estimate_ply_triangles(ascii.as_bytes()).unwrap(),
+            2,
+            "the header says there are two faces"
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use nalgebra::Vector4;
+use std::error::Error;
+use std::fs;
+
+// The PNG color types written here
+pub const GRAY: u8 = 0;
+pub const RGB: u8 = 2;
+pub const RGBA: u8 = 6;
+
+pub type Rgb = (u8, u8, u8);
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in bytes {
+        a = (a + u32::from(byte)) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Encodes rows of big endian samples as a PNG. The image data is stored
+// uncompressed, which every decoder reads and keeps this free of dependencies
+pub fn encode_png(width: usize, color_type: u8, bit_depth: u8, rows: &[Vec<u8>]) -> Vec<u8> {
+    let mut header = vec![];
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(rows.len() as u32).to_be_bytes());
+    header.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]); // Deflate, adaptive filtering, no interlacing
+
+    let mut raw = vec![];
+    for row in rows {
+        raw.push(0); // No filter
+        raw.extend_from_slice(row);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks: Vec<&[u8]> = raw.chunks(65535).collect();
+    if blocks.is_empty() {
+        blocks.push(&[]); // An image without rows still needs a final block to end the stream
+    }
+    for (index, block) in blocks.iter().enumerate() {
+        zlib.push((index + 1 == blocks.len()) as u8); // A stored block, the last one is marked final
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+// Writes view-space normals as an RGB PNG, each axis mapped from -1.0..=1.0
+// to 0..=255, pixels without a normal are black
+pub fn write_normal_png(
+    filename: &str,
+    normals: &[Vec<Vector4<f32>>],
+) -> Result<(), Box<dyn Error>> {
+    let channel = |n: f32| ((n * 0.5 + 0.5).clamp(0.0, 1.0) * 255.0).round() as u8;
+    let rows: Vec<Vec<u8>> = normals
+        .iter()
+        .map(|row| {
+            row.iter()
+                .flat_map(|normal| {
+                    if normal.norm() == 0.0 {
+                        vec![0, 0, 0]
+                    } else {
+                        vec![channel(normal.x), channel(normal.y), channel(normal.z)]
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let width = normals.first().map_or(0, Vec::len);
+    match fs::write(filename, encode_png(width, RGB, 8, &rows)) {
+        Err(e) => Err(format!("normal png: [{}] couldn't be written, {}", filename, e).into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+// Writes depths as a 16-bit grayscale PNG, near is white and far is dark
+// over the range of the finite depths, pixels without depth are black
+pub fn write_depth_png(filename: &str, depths: &[Vec<f32>]) -> Result<(), Box<dyn Error>> {
+    let drawn = || depths.iter().flatten().filter(|z| **z < f32::MAX);
+    let near = drawn().fold(f32::MAX, |near, &z| near.min(z));
+    let far = drawn().fold(f32::MIN, |far, &z| far.max(z));
+    let rows: Vec<Vec<u8>> = depths
+        .iter()
+        .map(|row| {
+            row.iter()
+                .flat_map(|&z| {
+                    let value = if z == f32::MAX {
+                        0
+                    } else if far > near {
+                        // Drawn pixels start at 1, so the farthest one isn't lost in the background
+                        1 + ((far - z) / (far - near) * 65534.0) as u16
+                    } else {
+                        u16::MAX
+                    };
+                    value.to_be_bytes().to_vec()
+                })
+                .collect()
+        })
+        .collect();
+    let width = depths.first().map_or(0, Vec::len);
+    match fs::write(filename, encode_png(width, GRAY, 16, &rows)) {
+        Err(e) => Err(format!("depth png: [{}] couldn't be written, {}", filename, e).into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+// Writes colors as an RGBA PNG, pixels without a color are left transparent
+// so layers can be composited over each other
+pub fn write_color_png(filename: &str, colors: &[Vec<Option<Rgb>>]) -> Result<(), Box<dyn Error>> {
+    let rows: Vec<Vec<u8>> = colors
+        .iter()
+        .map(|row| {
+            row.iter()
+                .flat_map(|color| match color {
+                    None => [0, 0, 0, 0],
+                    Some((r, g, b)) => [*r, *g, *b, 255],
+                })
+                .collect()
+        })
+        .collect();
+    let width = colors.first().map_or(0, Vec::len);
+    match fs::write(filename, encode_png(width, RGBA, 8, &rows)) {
+        Err(e) => Err(format!("png: [{}] couldn't be written, {}", filename, e).into()),
+        Ok(()) => Ok(()),
+    }
+}
+
+// Clears the colors within tolerance of key on every channel, to leave a solid background transparent
+pub fn chroma_key(colors: &mut [Vec<Option<Rgb>>], key: Rgb, tolerance: u8) {
+    let near = |a: u8, b: u8| (i16::from(a) - i16::from(b)).abs() <= i16::from(tolerance);
+    for color in colors.iter_mut().flatten() {
+        if let Some((r, g, b)) = *color {
+            if near(r, key.0) && near(g, key.1) && near(b, key.2) {
+                *color = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksums_match_their_known_values() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn one_pixel_image_has_a_valid_layout() {
+        let png = encode_png(1, RGB, 8, &[vec![255, 0, 0]]);
+        assert_eq!(png[..8], [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        // IHDR is 13 bytes: the width and height, then the depth, color type and three methods
+        assert_eq!(png[8..16], [0, 0, 0, 13, b'I', b'H', b'D', b'R']);
+        assert_eq!(png[16..29], [0, 0, 0, 1, 0, 0, 0, 1, 8, RGB, 0, 0, 0]);
+        assert_eq!(png[29..33], crc32(&png[12..29]).to_be_bytes());
+        // The one row is a filter byte and the pixel, in a single final stored block
+        let zlib = [
+            0x78, 0x01, 1, 4, 0, 0xFB, 0xFF, 0, 255, 0, 0, 0x03, 0x01, 0x01, 0x00,
+        ];
+        assert_eq!(png[33..37], (zlib.len() as u32).to_be_bytes());
+        assert_eq!(png[37..41], *b"IDAT");
+        assert_eq!(png[41..41 + zlib.len()], zlib);
+        assert_eq!(
+            png[png.len() - 12..],
+            [0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xAE, 0x42, 0x60, 0x82]
+        );
+    }
+
+    #[test]
+    fn empty_image_still_ends_its_stream() {
+        let png = encode_png(0, RGB, 8, &[]);
+        // A final stored block of no bytes, then the checksum of no data
+        let zlib = [0x78, 0x01, 1, 0, 0, 0xFF, 0xFF, 0, 0, 0, 1];
+        assert_eq!(png[33..37], (zlib.len() as u32).to_be_bytes());
+        assert_eq!(png[41..41 + zlib.len()], zlib);
+    }
+
+    #[test]
+    fn chroma_key_clears_only_colors_within_tolerance() {
+        let mut colors = vec![vec![
+            Some((25, 25, 25)),
+            Some((30, 20, 25)),
+            Some((31, 25, 25)),
+            None,
+            Some((200, 25, 25)),
+        ]];
+        chroma_key(&mut colors, (25, 25, 25), 5);
+        assert_eq!(
+            colors,
+            vec![vec![
+                None,
+                None,
+                Some((31, 25, 25)),
+                None,
+                Some((200, 25, 25))
+            ]]
+        );
+    }
+}
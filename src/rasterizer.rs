@@ -1,6 +1,121 @@
-use crate::context::Context;
-use crate::geometry::{SimpleMesh, Triangle};
-use nalgebra::{Matrix4, Vector4};
+use crate::context::{Context, DepthTiebreak};
+use crate::geometry::{Edge, SimpleMesh, Surface, Triangle};
+use crate::texture::Texture;
+use nalgebra::{Matrix3, Matrix4, Vector3, Vector4, U3};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Everything known about a fragment when it's shaded
+pub struct Fragment {
+    pub shade: f32, // How directly the face looks at the lights, 1.0 is head on at one
+    pub normal: Vector4<f32>, // In screen space
+    pub position: Vector4<f32>, // In object space
+    pub color: (u8, u8, u8), // The material's diffuse color
+    pub uv: Option<(f32, f32)>, // Texture coordinates, when the mesh has them
+    pub depth: f32, // Screen-space depth, smaller is nearer
+    pub area: f32,  // The screen-space area of the triangle, in pixels
+}
+
+// Decides the character and color of every fragment. Implement this to shade
+// meshes in a way the built-in shaders don't
+pub trait Shader {
+    fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8));
+}
+
+// The shaders picked with --shader
+pub enum BuiltinShader {
+    Default,
+    Matcap(Texture), // Looks the color up from a sphere image by the view-space normal
+    Checker(f32), // Alternates dark and light squares of this size in UV space, or cubes in object space
+    Density,      // Colors every face by its screen-space area, to show where a mesh is dense
+}
+
+impl Shader for BuiltinShader {
+    fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8)) {
+        let (shade, normal, position, color) = (
+            fragment.shade,
+            &fragment.normal,
+            &fragment.position,
+            fragment.color,
+        );
+        match self {
+            BuiltinShader::Default => (default_shader(shade), color),
+            BuiltinShader::Matcap(matcap) => {
+                let color = matcap.sample(0.5 - normal.x * 0.5, 0.5 - normal.y * 0.5);
+                (default_shader(luminance(color)), color)
+            }
+            BuiltinShader::Checker(size) => {
+                let cell = match fragment.uv {
+                    // Squares in texture space follow the surface, where the mesh is unwrapped
+                    Some((u, v)) => (u / size).floor() + (v / size).floor(),
+                    None => {
+                        (position.x / size).floor()
+                            + (position.y / size).floor()
+                            + (position.z / size).floor()
+                    }
+                };
+                if cell.rem_euclid(2.0) < 1.0 {
+                    (default_shader(shade), color)
+                } else {
+                    let dark = |c: u8| (f32::from(c) * 0.35) as u8;
+                    (
+                        default_shader(shade * 0.5),
+                        (dark(color.0), dark(color.1), dark(color.2)),
+                    )
+                }
+            }
+            // Faces from a single pixel up to about a thousand spread over the colormap
+            BuiltinShader::Density => (
+                default_shader(shade),
+                colormap((fragment.area.max(1.0).log2() / 10.0).min(1.0)),
+            ),
+        }
+    }
+}
+
+// Maps 0.0..=1.0 from blue through cyan, green and yellow to red
+pub fn colormap(t: f32) -> (u8, u8, u8) {
+    let ramp = |center: f32| ((1.5 - (t * 4.0 - center).abs()).clamp(0.0, 1.0) * 255.0) as u8;
+    (ramp(3.0), ramp(2.0), ramp(1.0))
+}
+
+pub fn luminance(color: (u8, u8, u8)) -> f32 {
+    (0.2126 * f32::from(color.0) + 0.7152 * f32::from(color.1) + 0.0722 * f32::from(color.2))
+        / 255.0
+}
+
+// Orders fragments by their color's luminance, then by how dense their character is
+fn brightness(pixel: (char, (u8, u8, u8))) -> (f32, Option<usize>) {
+    (luminance(pixel.1), density(pixel.0))
+}
+
+// How much of a cell the character fills, None for characters off the shading ramp
+pub fn density(character: char) -> Option<usize> {
+    " .:-=+*#%@".find(character)
+}
+
+// Averages a fragment into the color of the layers already at its depth, out of this many layers in all,
+// so coplanar faces look the same whatever order they're drawn in
+fn blend(
+    below: (char, (u8, u8, u8)),
+    above: (char, (u8, u8, u8)),
+    layers: u16,
+) -> (char, (u8, u8, u8)) {
+    let weight = 1.0 / f32::from(layers.max(1));
+    let mix = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * weight).round() as u8;
+    let character = if density(above.0) > density(below.0) {
+        above.0
+    } else {
+        below.0
+    };
+    (
+        character,
+        (
+            mix((below.1).0, (above.1).0),
+            mix((below.1).1, (above.1).1),
+            mix((below.1).2, (above.1).2),
+        ),
+    )
+}
 
 pub fn default_shader(shade: f32) -> char {
     if shade <= 0.20 {
@@ -26,6 +141,10 @@ pub fn default_shader(shade: f32) -> char {
     }
 }
 
+// How close two depths are, relative to their size, to count as the same depth. Coplanar faces
+// interpolate their depth from different vertices, so they're rarely exactly equal
+const DEPTH_TIE_TOLERANCE: f32 = 1.0e-4;
+
 // Used in rasterization
 fn orient(a: &Vector4<f32>, b: &Vector4<f32>, c: &Vector4<f32>) -> f32 {
     (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
@@ -35,24 +154,126 @@ fn orient_triangle(triangle: &Triangle) -> f32 {
     orient(&triangle.v1, &triangle.v2, &triangle.v3)
 }
 
+// Carries an object-space normal into screen space the way the cross product of a triangle's
+// transformed sides carries its face's, so a point gets the normal of the faces around it
+fn screen_normal(screen: &Matrix4<f32>, normal: &Vector4<f32>) -> Option<Vector4<f32>> {
+    let linear: Matrix3<f32> = screen.fixed_slice::<U3, U3>(0, 0).into_owned();
+    let cofactor = linear.try_inverse()?.transpose() * linear.determinant();
+    let normal = (cofactor * normal.xyz()).try_normalize(f32::EPSILON)?;
+    Some(Vector4::new(normal.x, normal.y, normal.z, 0.0))
+}
+
+// A screen-space normal as the view-space one exported, x right, y up and z towards the camera.
+// Winding makes the screen-space normal point into front faces, and screen y points down
+fn view_normal(normal: &Vector4<f32>) -> Vector4<f32> {
+    Vector4::new(-normal.x, normal.y, normal.z, 0.0)
+}
+
+// How directly a screen-space normal faces the lights, summed over all of them
+fn lighting(normal: &Vector4<f32>, lights: &[Vector3<f32>]) -> f32 {
+    let normal = view_normal(normal).xyz();
+    lights.iter().map(|light| normal.dot(light).max(0.0)).sum()
+}
+
+// The mesh's own rotation, it turns in object space before the turntable does
+fn spin(mesh: &SimpleMesh) -> Matrix4<f32> {
+    mesh.spin
+        .as_ref()
+        .map_or_else(Matrix4::identity, |spin| spin.rotation())
+}
+
 // Writes multiple meshes to context
-pub fn draw_mesh<F>(context: &mut Context, mesh: &SimpleMesh, transform: Matrix4<f32>, shader: F)
-where
-    F: Fn(f32) -> char,
-{
+pub fn draw_mesh<S: Shader + ?Sized>(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    shader: &S,
+    lights: &[Vector3<f32>],
+) {
+    draw_mesh_cancellable(
+        context,
+        mesh,
+        transform,
+        shader,
+        lights,
+        &AtomicBool::new(false),
+    );
+}
+
+// The same as draw_mesh, but stops between triangles once cancel is set, returns false if it did
+pub fn draw_mesh_cancellable<S: Shader + ?Sized>(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    shader: &S,
+    lights: &[Vector3<f32>],
+    cancel: &AtomicBool,
+) -> bool {
+    // A mesh's own shader overrides the one it's drawn with
+    match mesh.shader.as_deref() {
+        Some(own) => draw_triangles(context, mesh, transform, own, lights, cancel),
+        None => draw_triangles(context, mesh, transform, shader, lights, cancel),
+    }
+}
+
+fn draw_triangles<S: Shader + ?Sized>(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    shader: &S,
+    lights: &[Vector3<f32>],
+    cancel: &AtomicBool,
+) -> bool {
+    let transform = mesh.placement * transform * spin(mesh);
     for triangle in &mesh.triangles {
-        draw_triangle(context, &triangle, transform, &shader);
+        if cancel.load(Ordering::Relaxed) {
+            return false;
+        }
+        draw_triangle(context, triangle, transform, shader, lights, mesh.surface);
     }
+    true
 }
 
-pub fn draw_triangle<F>(
+// Cuts away everything on the far side of a plane along its normal, to see inside meshes
+#[derive(Clone, Copy)]
+pub struct ClipPlane {
+    pub yaw: f32,    // The normal's turn around the y axis, in radians
+    pub pitch: f32,  // The normal's tilt up from the xz plane, in radians
+    pub offset: f32, // How far along the normal the plane is from the origin, in object space
+}
+
+impl ClipPlane {
+    pub fn normal(&self) -> Vector3<f32> {
+        Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        )
+    }
+    pub fn cuts(&self, position: &Vector4<f32>) -> bool {
+        self.normal().dot(&position.xyz()) > self.offset
+    }
+}
+
+impl std::fmt::Display for ClipPlane {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let normal = self.normal();
+        write!(
+            f,
+            "clip plane at {:.2} along ({:.2}, {:.2}, {:.2})",
+            self.offset, normal.x, normal.y, normal.z
+        )
+    }
+}
+
+pub fn draw_triangle<S: Shader + ?Sized>(
     context: &mut Context,
     triangle: &Triangle,
     transform: Matrix4<f32>,
-    shader: F,
-) where
-    F: Fn(f32) -> char,
-{
+    shader: &S,
+    lights: &[Vector3<f32>],
+    surface: Surface,
+) {
     let mut dist_triangle = triangle.clone();
     dist_triangle.mul(context.utransform * transform);
     let aabb = dist_triangle.to_aabb(); // Calculate triangle bounds
@@ -61,10 +282,47 @@ pub fn draw_triangle<F>(
         aabb.min[1].max(1.0).ceil() as usize,
     );
     let maxs: (usize, usize) = (
-        (aabb.max[0] * 2.0).min((context.width - 1) as f32).ceil() as usize,
+        aabb.max[0].min((context.width / 2) as f32).ceil() as usize, // Pixels are two cells wide
         aabb.max[1].min((context.height - 1) as f32).ceil() as usize,
     );
-    let a = 1.0 / orient_triangle(&dist_triangle);
+    let area = orient_triangle(&dist_triangle);
+    if area <= 0.0 {
+        match context.backface_color {
+            Some(color) if area < 0.0 => {
+                // Wound the other way round the back face turns towards the camera
+                let mut flipped = triangle.clone();
+                std::mem::swap(&mut flipped.v2, &mut flipped.v3);
+                if let Some(uvs) = &mut flipped.uvs {
+                    uvs.swap(1, 2);
+                }
+                flipped.color = color;
+                // Shown in exactly the color asked for, whatever the material gives off
+                draw_triangle(
+                    context,
+                    &flipped,
+                    transform,
+                    shader,
+                    lights,
+                    Surface::default(),
+                );
+            }
+            // Back faces never pass the edge tests below, so skip them early
+            _ => context.stats.triangles_culled += 1,
+        }
+        return;
+    }
+    context.stats.triangles_drawn += 1;
+    let a = 1.0 / area;
+    let side = |a: &Vector4<f32>, b: &Vector4<f32>| (b.xy() - a.xy()).norm();
+    let sides = (
+        side(&dist_triangle.v2, &dist_triangle.v3),
+        side(&dist_triangle.v3, &dist_triangle.v1),
+        side(&dist_triangle.v1, &dist_triangle.v2),
+    );
+    let normal = dist_triangle.normal();
+    let view_normal = view_normal(&normal);
+    let lit = surface.shade(lighting(&normal, lights));
+    let color = surface.color(dist_triangle.color);
 
     for y in mins.1..maxs.1 {
         for x in mins.0..maxs.0 {
@@ -73,21 +331,609 @@ pub fn draw_triangle<F>(
             let w1 = orient(&dist_triangle.v3, &dist_triangle.v1, &p);
             let w2 = orient(&dist_triangle.v1, &dist_triangle.v2, &p);
             if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
-                let pixel_shade = dist_triangle.normal().z * a * (w0 + w1 + w2);
+                let pixel_shade = lit * a * (w0 + w1 + w2);
                 let z = dist_triangle.v1[2]
                     + a * (w1 * (dist_triangle.v2[2] - dist_triangle.v1[2])
                         + w2 * (dist_triangle.v3[2] - dist_triangle.v1[2]));
                 let id = y * context.width + x * 2;
-                if z < context.z_buffer[id] {
+                let tie =
+                    (z - context.z_buffer[id]).abs() <= DEPTH_TIE_TOLERANCE * z.abs().max(1.0);
+                let kept = if tie {
+                    context.depth_tiebreak != DepthTiebreak::First
+                } else {
+                    z < context.z_buffer[id]
+                };
+                if kept {
+                    // Orthographic projection keeps screen-space barycentrics valid in object space
+                    let position = (triangle.v1 * w0 + triangle.v2 * w1 + triangle.v3 * w2) * a;
+                    if context
+                        .clip_plane
+                        .is_some_and(|plane| plane.cuts(&position))
+                    {
+                        continue;
+                    }
+                    let mut pixel = shader.shade(&Fragment {
+                        shade: pixel_shade,
+                        normal: normal.into_inner(),
+                        position,
+                        color,
+                        uv: triangle.uvs.map(|uvs| {
+                            (
+                                (uvs[0].0 * w0 + uvs[1].0 * w1 + uvs[2].0 * w2) * a,
+                                (uvs[0].1 * w0 + uvs[1].1 * w1 + uvs[2].1 * w2) * a,
+                            )
+                        }),
+                        depth: z,
+                        area: area * 0.5,
+                    });
+                    // Each weight is the distance to the opposite side times that side's length
+                    let edge = w0 < sides.0 * 0.5 || w1 < sides.1 * 0.5 || w2 < sides.2 * 0.5;
+                    match (edge, context.edge_char, context.fill_char) {
+                        (true, Some(character), _) | (false, _, Some(character)) => {
+                            pixel.0 = character
+                        }
+                        _ => {}
+                    }
+                    if tie
+                        && context.depth_tiebreak == DepthTiebreak::Brightest
+                        && brightness(pixel) <= brightness(context.frame_buffer[id])
+                    {
+                        continue;
+                    }
+                    if let Some(coverage) = &mut context.coverage_buffer {
+                        coverage[id] = if tie { coverage[id] + 1 } else { 1 };
+                        if tie && context.depth_tiebreak == DepthTiebreak::Blend {
+                            pixel = blend(context.frame_buffer[id], pixel, coverage[id]);
+                        }
+                    }
                     context.z_buffer[id] = z;
-                    let pixel = (shader(pixel_shade), dist_triangle.color);
-                    context.frame_buffer[id] = pixel;
-                    context.frame_buffer[id + 1] = pixel;
+                    context.stats.fragments += 1;
+                    if let Some(normals) = &mut context.normal_buffer {
+                        normals[id] = view_normal;
+                    }
+                    context.plot(id, pixel);
+                }
+            }
+        }
+    }
+}
+
+// The shape of the pixels a point covers
+#[derive(Clone, Copy, PartialEq)]
+pub enum Falloff {
+    Square,
+    Circle,
+    Gaussian, // A circle that fades out towards its edge
+}
+
+// How every point is drawn
+#[derive(Clone, Copy)]
+pub struct PointStyle {
+    pub size: f32,               // Pixels across
+    pub splat_size: Option<f32>, // Grows nearer points up to this many pixels across, using the context's depth range
+    pub falloff: Falloff,
+}
+
+// Draws every point of a mesh as a depth tested dot
+pub fn draw_points<S: Shader + ?Sized>(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    shader: &S,
+    lights: &[Vector3<f32>],
+    style: PointStyle,
+) {
+    match mesh.shader.as_deref() {
+        Some(own) => draw_mesh_points(context, mesh, transform, own, lights, style),
+        None => draw_mesh_points(context, mesh, transform, shader, lights, style),
+    }
+}
+
+fn draw_mesh_points<S: Shader + ?Sized>(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    shader: &S,
+    lights: &[Vector3<f32>],
+    style: PointStyle,
+) {
+    let screen = context.utransform * mesh.placement * transform * spin(mesh);
+    let (near, far) = context.depth_range;
+    for point in &mesh.points {
+        if context
+            .clip_plane
+            .is_some_and(|plane| plane.cuts(&point.position))
+        {
+            continue;
+        }
+        let center = screen * point.position;
+        // Points without a normal face the camera
+        let normal = screen_normal(&screen, &point.normal).unwrap_or_else(Vector4::z);
+        let lit = mesh.surface.shade(lighting(&normal, lights));
+        let size = match style.splat_size {
+            Some(splat_size) if far > near => {
+                let nearness = ((far - center.z) / (far - near)).clamp(0.0, 1.0);
+                style.size + (splat_size - style.size).max(0.0) * nearness
+            }
+            _ => style.size,
+        };
+        let radius = size * 0.5;
+        let reach = (size.round() as i64 - 1).max(0);
+        let (cx, cy) = (center.x.round() as i64, center.y.round() as i64);
+        // A square of n pixels across starts (n - 1) / 2 pixels before the center
+        for dy in -reach / 2..=reach - reach / 2 {
+            for dx in -reach / 2..=reach - reach / 2 {
+                let distance = ((dx * dx + dy * dy) as f32).sqrt();
+                let shade = match style.falloff {
+                    Falloff::Square => lit,
+                    _ if distance > radius.max(0.5) => continue,
+                    Falloff::Circle => lit,
+                    Falloff::Gaussian => lit * (-2.0 * (distance / radius).powi(2)).exp(),
+                };
+                let (x, y) = (cx + dx, cy + dy);
+                if x < 1
+                    || y < 1
+                    || x as usize * 2 + 1 >= context.width
+                    || y as usize >= context.height
+                {
+                    continue;
+                }
+                let id = y as usize * context.width + x as usize * 2;
+                if center.z < context.z_buffer[id] {
+                    context.z_buffer[id] = center.z;
+                    context.stats.fragments += 1;
+                    if let Some(normals) = &mut context.normal_buffer {
+                        normals[id] = view_normal(&normal);
+                    }
+                    let pixel = shader.shade(&Fragment {
+                        shade,
+                        normal,
+                        position: point.position,
+                        color: mesh.surface.color(point.color),
+                        uv: None,
+                        depth: center.z,
+                        area: 0.0,
+                    });
+                    context.plot(id, pixel);
+                }
+            }
+        }
+    }
+}
+
+// How far in front of the depth buffer a line may be and still be drawn, so edges aren't hidden by their own faces
+const LINE_DEPTH_BIAS: f32 = 1.0;
+
+// Picks a character that follows the line's screen-space direction
+fn line_char(dx: f32, dy: f32) -> char {
+    if dy.abs() < dx.abs() * 0.4 {
+        '-'
+    } else if dx.abs() < dy.abs() * 0.4 {
+        '|'
+    } else if (dx > 0.0) == (dy > 0.0) {
+        '\\' // Screen space Y points down
+    } else {
+        '/'
+    }
+}
+
+// Draws a depth tested line between two screen-space points
+pub fn draw_line(context: &mut Context, a: &Vector4<f32>, b: &Vector4<f32>, color: (u8, u8, u8)) {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+    let character = line_char(dx, dy);
+    let (near, far) = context.depth_range;
+    for step in 0..=steps {
+        let p = a + (b - a) * (step as f32 / steps as f32);
+        let (x, y) = (p.x.round(), p.y.round());
+        if x < 1.0 || y < 1.0 || x * 2.0 + 1.0 >= context.width as f32 || y >= context.height as f32
+        {
+            continue;
+        }
+        let id = y as usize * context.width + x as usize * 2;
+        if p.z <= context.z_buffer[id] + LINE_DEPTH_BIAS {
+            let color = match context.wire_depth_colors {
+                Some([near_color, far_color]) if far > near => {
+                    let t = ((p.z - near) / (far - near)).clamp(0.0, 1.0);
+                    let mix =
+                        |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * t) as u8;
+                    (
+                        mix(near_color.0, far_color.0),
+                        mix(near_color.1, far_color.1),
+                        mix(near_color.2, far_color.2),
+                    )
                 }
+                _ => color,
+            };
+            context.plot(id, (character, color));
+            if let Some(wire) = &mut context.wire_buffer {
+                wire[id] = (character, color);
             }
         }
-        if context.image {
-            context.frame_buffer[y * context.width + 1] = ('\n', (0, 0, 0));
+    }
+}
+
+// Keeps an edge if it outlines the mesh (between a front and a back face, or on an open boundary
+// facing the camera), or if its faces fold sharper than crease_angle
+fn is_feature_edge(edge: &Edge, front_facing: &[bool], crease_angle: f32) -> bool {
+    match edge.faces {
+        (face, None) => front_facing[face],
+        (face, Some(other)) => {
+            front_facing[face] != front_facing[other]
+                || ((front_facing[face] || front_facing[other]) && edge.dihedral > crease_angle)
+        }
+    }
+}
+
+// Draws the silhouette and crease edges of a mesh, its depth should already be in the context
+pub fn draw_feature_edges(
+    context: &mut Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+    crease_angle: f32,
+) {
+    let screen = context.utransform * mesh.placement * transform * spin(mesh);
+    let front_facing: Vec<bool> = mesh
+        .triangles
+        .iter()
+        .map(|triangle| {
+            let mut dist_triangle = triangle.clone();
+            orient_triangle(dist_triangle.mul(screen)) > 0.0
+        })
+        .collect();
+    for edge in &mesh.edges {
+        if is_feature_edge(edge, &front_facing, crease_angle) {
+            let color = mesh.triangles[edge.faces.0].color;
+            draw_line(context, &(screen * edge.v1), &(screen * edge.v2), color);
+        }
+    }
+}
+
+// Draws every edge of a mesh, facing the camera or not, so it reads as a full wireframe
+pub fn draw_wireframe(context: &mut Context, mesh: &SimpleMesh, transform: Matrix4<f32>) {
+    let screen = context.utransform * mesh.placement * transform * spin(mesh);
+    for edge in &mesh.edges {
+        let color = mesh.triangles[edge.faces.0].color;
+        draw_line(context, &(screen * edge.v1), &(screen * edge.v2), color);
+    }
+}
+
+// The screen-space corners of a mesh's bounding box, bit 0, 1 and 2 of the index pick max x, y and z
+fn bounding_box_corners(
+    context: &Context,
+    mesh: &SimpleMesh,
+    transform: Matrix4<f32>,
+) -> Vec<Vector4<f32>> {
+    let screen = context.utransform * mesh.placement * transform * spin(mesh);
+    let (min, max) = (&mesh.bounding_box.min, &mesh.bounding_box.max);
+    (0..8)
+        .map(|i| {
+            screen
+                * Vector4::new(
+                    if i & 1 == 0 { min.x } else { max.x },
+                    if i & 2 == 0 { min.y } else { max.y },
+                    if i & 4 == 0 { min.z } else { max.z },
+                    1.0,
+                )
+        })
+        .collect()
+}
+
+// The nearest and farthest screen-space depth any of the meshes can reach
+pub fn depth_range(
+    context: &Context,
+    meshes: &[SimpleMesh],
+    transform: Matrix4<f32>,
+) -> (f32, f32) {
+    meshes
+        .iter()
+        .flat_map(|mesh| bounding_box_corners(context, mesh, transform))
+        .fold((f32::MAX, f32::MIN), |(near, far), corner| {
+            (near.min(corner.z), far.max(corner.z))
+        })
+}
+
+// Draws the 12 edges of a mesh's bounding box in place of the mesh
+pub fn draw_bounding_box(context: &mut Context, mesh: &SimpleMesh, transform: Matrix4<f32>) {
+    let corners = bounding_box_corners(context, mesh, transform);
+    let color = mesh
+        .triangles
+        .first()
+        .map_or((255, 255, 255), |tri| tri.color);
+    for i in 0..8 {
+        for axis in &[1, 2, 4] {
+            if i & axis == 0 {
+                // Every corner connects to the corner one step along each axis
+                draw_line(context, &corners[i], &corners[i | axis], color);
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Scene;
+    use nalgebra::Rotation3;
+    use std::cell::RefCell;
+
+    // Keeps the normal of every fragment it shades
+    struct NormalRecorder(RefCell<Vec<Vector4<f32>>>);
+
+    impl Shader for NormalRecorder {
+        fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8)) {
+            self.0.borrow_mut().push(fragment.normal);
+            ('@', fragment.color)
+        }
+    }
+
+    fn assert_close(a: &Vector4<f32>, b: &Vector4<f32>) {
+        assert!((a - b).norm() < 1.0e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn points_get_the_normals_of_their_faces() {
+        let triangle = Triangle {
+            color: (255, 255, 255),
+            v1: Vector4::new(-1.0, -1.0, 0.2, 1.0),
+            v2: Vector4::new(0.1, 1.0, 0.1, 1.0),
+            v3: Vector4::new(1.0, -0.8, -0.3, 1.0),
+            diagonals: [false; 3],
+            uvs: None,
+        };
+        let mut mesh = SimpleMesh::new(String::new(), vec![triangle], vec![]);
+        mesh.build_points(); // Every corner gets the face's normal
+        let scene = Scene::new(vec![mesh], (0.0, 0.0, 0.0, 0.0));
+        let (mesh, lights) = (&scene.meshes[0], &scene.lights);
+        let rotation = Rotation3::from_euler_angles(0.3, 0.5, 0.1).to_homogeneous();
+        let mut context = Context::blank(true);
+        context.width = 40;
+        context.height = 20;
+        context.normal_buffer = Some(vec![]);
+        context.update((0, 0), &scene).unwrap();
+
+        // Each pass returns the normals its fragments were shaded with, and the exported ones
+        let mut drawn = |draw: &dyn Fn(&mut Context, &dyn Shader)| {
+            let shader = NormalRecorder(RefCell::new(vec![]));
+            context.clear();
+            draw(&mut context, &shader);
+            let exported: Vec<Vector4<f32>> = context
+                .normal_buffer
+                .iter()
+                .flatten()
+                .filter(|normal| normal.norm() > 0.0)
+                .copied()
+                .collect();
+            (shader.0.into_inner(), exported)
+        };
+        let (face, face_exported) =
+            drawn(&|context, shader| draw_mesh(context, mesh, rotation, shader, lights));
+        let style = PointStyle {
+            size: 1.0,
+            splat_size: None,
+            falloff: Falloff::Square,
+        };
+        let (points, points_exported) =
+            drawn(&|context, shader| draw_points(context, mesh, rotation, shader, lights, style));
+
+        assert!(!face.is_empty() && !points.is_empty());
+        for normal in &points {
+            assert_close(normal, &face[0]);
+        }
+        assert!(!face_exported.is_empty() && !points_exported.is_empty());
+        for normal in &points_exported {
+            assert_close(normal, &face_exported[0]);
+        }
+    }
+
+    // Draws every fragment with one character, to tell which shader drew it
+    struct Solid(char);
+
+    impl Shader for Solid {
+        fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8)) {
+            (self.0, fragment.color)
+        }
+    }
+
+    #[test]
+    fn shaders_from_outside_plug_into_scenes_and_meshes() {
+        let triangle = Triangle {
+            color: (255, 255, 255),
+            v1: Vector4::new(-1.0, -1.0, 0.0, 1.0),
+            v2: Vector4::new(0.0, 1.0, 0.0, 1.0),
+            v3: Vector4::new(1.0, -1.0, 0.0, 1.0),
+            diagonals: [false; 3],
+            uvs: None,
+        };
+        let mesh = SimpleMesh::new(String::new(), vec![triangle], vec![]);
+        let mut scene = Scene::new(vec![mesh], (0.0, 0.0, 0.0, 0.0));
+        let mut context = Context::blank(true);
+        context.width = 40;
+        context.height = 20;
+        context.update((0, 0), &scene).unwrap();
+        let mut drawn = |scene: &Scene| {
+            context.clear();
+            scene.render(&mut context);
+            let mut chars: Vec<char> = context
+                .frame_buffer
+                .iter()
+                .map(|cell| cell.0)
+                .filter(|c| !c.is_whitespace())
+                .collect();
+            chars.dedup();
+            chars
+        };
+
+        scene.shader = Box::new(Solid('a'));
+        assert_eq!(drawn(&scene), vec!['a']);
+        scene.meshes[0].shader = Some(Box::new(Solid('b')));
+        assert_eq!(drawn(&scene), vec!['b']);
+    }
+
+    // Keeps how lit every fragment it shades is
+    struct ShadeRecorder(RefCell<Vec<f32>>);
+
+    impl Shader for ShadeRecorder {
+        fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8)) {
+            self.0.borrow_mut().push(fragment.shade);
+            ('@', fragment.color)
+        }
+    }
+
+    // Keeps where every fragment it shades is
+    struct PositionRecorder(RefCell<Vec<Vector4<f32>>>);
+
+    impl Shader for PositionRecorder {
+        fn shade(&self, fragment: &Fragment) -> (char, (u8, u8, u8)) {
+            self.0.borrow_mut().push(fragment.position);
+            ('@', fragment.color)
+        }
+    }
+
+    #[test]
+    fn faces_are_lit_by_every_light_they_face() {
+        // Faces the camera head on
+        let triangle = Triangle {
+            color: (255, 255, 255),
+            v1: Vector4::new(-1.0, -1.0, 0.0, 1.0),
+            v2: Vector4::new(0.0, 1.0, 0.0, 1.0),
+            v3: Vector4::new(1.0, -1.0, 0.0, 1.0),
+            diagonals: [false; 3],
+            uvs: None,
+        };
+        let mut scene = Scene::new(
+            vec![SimpleMesh::new(String::new(), vec![triangle], vec![])],
+            (0.0, 0.0, 0.0, 0.0),
+        );
+        let mut context = Context::blank(true);
+        context.width = 40;
+        context.height = 20;
+        context.update((0, 0), &scene).unwrap();
+        let mut shades = |lights: Vec<Vector3<f32>>| {
+            scene.lights = lights;
+            let shader = ShadeRecorder(RefCell::new(vec![]));
+            context.clear();
+            draw_mesh(
+                &mut context,
+                &scene.meshes[0],
+                scene.rotation(),
+                &shader,
+                &scene.lights,
+            );
+            let shades = shader.0.into_inner();
+            assert!(!shades.is_empty());
+            shades.iter().sum::<f32>() / shades.len() as f32
+        };
+
+        let side = Vector3::new(1.0, 0.0, 1.0).normalize();
+        assert!((shades(vec![Vector3::z()]) - 1.0).abs() < 1.0e-4);
+        assert!((shades(vec![side]) - side.z).abs() < 1.0e-4);
+        assert!((shades(vec![Vector3::z(), side]) - 1.0 - side.z).abs() < 1.0e-4);
+        assert_eq!(shades(vec![-Vector3::z()]), 0.0); // Behind the face
+        assert_eq!(shades(vec![]), 0.0);
+    }
+
+    #[test]
+    fn emissive_faces_glow_and_unlit_ones_ignore_the_light() {
+        let triangle = Triangle {
+            color: (255, 0, 0),
+            v1: Vector4::new(-1.0, -1.0, 0.0, 1.0),
+            v2: Vector4::new(0.0, 1.0, 0.0, 1.0),
+            v3: Vector4::new(1.0, -1.0, 0.0, 1.0),
+            diagonals: [false; 3],
+            uvs: None,
+        };
+        let mut scene = Scene::new(
+            vec![SimpleMesh::new(String::new(), vec![triangle], vec![])],
+            (0.0, 0.0, 0.0, 0.0),
+        );
+        scene.lights = vec![-Vector3::z()]; // Behind the face, it gets no light
+        let mut context = Context::blank(true);
+        context.width = 40;
+        context.height = 20;
+        context.update((0, 0), &scene).unwrap();
+        let mut shaded = |surface: Surface| {
+            scene.meshes[0].surface = surface;
+            let shader = ShadeRecorder(RefCell::new(vec![]));
+            context.clear();
+            draw_mesh(
+                &mut context,
+                &scene.meshes[0],
+                scene.rotation(),
+                &shader,
+                &scene.lights,
+            );
+            let shades = shader.0.into_inner();
+            let colors: Vec<_> = context
+                .frame_buffer
+                .iter()
+                .filter(|cell| cell.0 == '@')
+                .map(|cell| cell.1)
+                .collect();
+            (shades[0], colors[0])
+        };
+
+        assert_eq!(shaded(Surface::default()), (0.0, (255, 0, 0)));
+        let (shade, color) = shaded(Surface {
+            emissive: (0, 255, 0),
+            unlit: false,
+        });
+        assert!(shade > 0.5);
+        assert_eq!(color, (255, 255, 0));
+        let unlit = Surface {
+            emissive: (0, 255, 0),
+            unlit: true,
+        };
+        assert_eq!(shaded(unlit), (1.0, (255, 0, 0)));
+    }
+
+    #[test]
+    fn the_clip_plane_cuts_away_what_is_beyond_it() {
+        let triangle = Triangle {
+            color: (255, 255, 255),
+            v1: Vector4::new(-1.0, -1.0, 0.0, 1.0),
+            v2: Vector4::new(0.0, 1.0, 0.0, 1.0),
+            v3: Vector4::new(1.0, -1.0, 0.0, 1.0),
+            diagonals: [false; 3],
+            uvs: None,
+        };
+        let scene = Scene::new(
+            vec![SimpleMesh::new(String::new(), vec![triangle], vec![])],
+            (0.0, 0.0, 0.0, 0.0),
+        );
+        let mut context = Context::blank(true);
+        context.width = 40;
+        context.height = 20;
+        context.update((0, 0), &scene).unwrap();
+        let mut positions = |clip_plane: Option<ClipPlane>| {
+            context.clip_plane = clip_plane;
+            let shader = PositionRecorder(RefCell::new(vec![]));
+            context.clear();
+            draw_mesh(
+                &mut context,
+                &scene.meshes[0],
+                scene.rotation(),
+                &shader,
+                &scene.lights,
+            );
+            shader.0.into_inner()
+        };
+
+        let whole = positions(None);
+        // Turned to face +x, through the middle of the triangle
+        let halved = positions(Some(ClipPlane {
+            yaw: std::f32::consts::FRAC_PI_2,
+            pitch: 0.0,
+            offset: 0.0,
+        }));
+        assert!(!halved.is_empty() && halved.len() < whole.len());
+        assert!(halved.iter().all(|position| position.x <= 1.0e-4));
+        // Moved past the whole triangle along its normal
+        let gone = positions(Some(ClipPlane {
+            yaw: 0.0,
+            pitch: 0.0,
+            offset: -0.5,
+        }));
+        assert!(gone.is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+use crate::background::Background;
+use crate::context::Context;
+use crate::geometry::SimpleMesh;
+use crate::rasterizer::{
+    depth_range, draw_bounding_box, draw_feature_edges, draw_mesh_cancellable, draw_points,
+    draw_wireframe, BuiltinShader, PointStyle, Shader,
+};
+use nalgebra::{Matrix4, Rotation3, UnitQuaternion, Vector3};
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// Where the meshes are looked at from, the camera always looks at the origin and fits them to the frame
+#[derive(Clone, Copy)]
+pub struct Camera {
+    pub turntable: (f32, f32, f32, f32), // Euler angles in radians, then the speed around y in rad/s
+    pub zoom: f32,                       // Multiplies the auto-fit scale
+}
+
+// Everything that's drawn and how: the meshes with their own placement and spin, the camera that
+// looks at them, the lights and background, and the passes they're drawn with
+pub struct Scene {
+    pub meshes: Vec<SimpleMesh>,
+    pub camera: Camera,
+    pub lights: Vec<Vector3<f32>>, // Unit directions towards each light in view space, z points at the camera
+    pub background: Background,
+    pub time: f32,                  // Seconds the scene has been animated for
+    pub shader: Box<dyn Shader>,    // Used for every mesh without a shader of its own
+    pub feature_edges: Option<f32>, // Draws only the edges sharper than this crease angle
+    pub hide_diagonals: bool,
+    pub points: Option<PointStyle>, // Draws the meshes' points instead of their faces
+    pub bbox_only: bool,
+    pub flips: (bool, bool),
+    pub rainbow: Option<f32>, // Turns the hues this many degrees per second
+}
+
+impl Scene {
+    pub fn new(meshes: Vec<SimpleMesh>, turntable: (f32, f32, f32, f32)) -> Scene {
+        Scene {
+            meshes,
+            camera: Camera {
+                turntable,
+                zoom: 1.0,
+            },
+            lights: vec![Vector3::z()], // A single light behind the camera, faces are lit as head on as they look at it
+            background: Background::default(),
+            time: 0.0,
+            shader: Box::new(BuiltinShader::Default),
+            feature_edges: None,
+            hide_diagonals: false,
+            points: None,
+            bbox_only: false,
+            flips: (false, false),
+            rainbow: None,
+        }
+    }
+    // Finds the edges the feature edge and wireframe passes draw, and the points the point pass does
+    pub fn build_edges(&mut self) {
+        for mesh in &mut self.meshes {
+            if self.points.is_some() {
+                mesh.build_points();
+            }
+            if self.feature_edges.is_some() || mesh.wireframe {
+                mesh.build_edges();
+                if self.hide_diagonals {
+                    mesh.edges.retain(|edge| !edge.diagonal);
+                }
+            }
+        }
+    }
+    // Swaps in other meshes, with the edges and points the scene draws them with
+    pub fn load(&mut self, meshes: Vec<SimpleMesh>) {
+        self.meshes = meshes;
+        self.build_edges();
+    }
+    pub fn rotation(&self) -> Matrix4<f32> {
+        let turntable = self.camera.turntable;
+        Rotation3::from_euler_angles(turntable.0, turntable.1, turntable.2).to_homogeneous()
+    }
+    // Turns the turntable and every spinning mesh as if dt seconds passed
+    pub fn advance(&mut self, dt: f32) {
+        self.time += dt;
+        self.camera.turntable.1 += self.camera.turntable.3 * dt;
+        for spin in self.meshes.iter_mut().filter_map(|mesh| mesh.spin.as_mut()) {
+            spin.angle += spin.speed * dt;
+        }
+    }
+    // Points the turntable at this orientation, keeping its speed
+    pub fn look_from(&mut self, orientation: UnitQuaternion<f32>) {
+        let (x, y, z) = orientation.euler_angles();
+        self.camera.turntable = (x, y, z, self.camera.turntable.3);
+    }
+    // Turns the turntable back to these angles and speed, and every spinning mesh back to its start
+    pub fn reset_view(&mut self, turntable: (f32, f32, f32, f32)) {
+        self.camera.turntable = turntable;
+        for spin in self.meshes.iter_mut().filter_map(|mesh| mesh.spin.as_mut()) {
+            spin.angle = 0.0;
+        }
+    }
+    // Draws a frame into the context, which should already be sized with update
+    pub fn render(&self, context: &mut Context) {
+        self.render_cancellable(context, &AtomicBool::new(false));
+    }
+    // The same as render, but stops between meshes and triangles once cancel is set, returns false
+    // if it did and the frame is only partly drawn
+    pub fn render_cancellable(&self, context: &mut Context, cancel: &AtomicBool) -> bool {
+        let cancelled = || cancel.load(Ordering::Relaxed);
+        let rot = self.rotation();
+        context.clear(); // This clears the z and frame buffer
+        if context.wire_depth_colors.is_some()
+            || matches!(
+                self.points,
+                Some(PointStyle {
+                    splat_size: Some(_),
+                    ..
+                })
+            )
+        {
+            context.depth_range = depth_range(context, &self.meshes, rot);
+        }
+        let shader = self.shader.as_ref();
+        if self.bbox_only {
+            for mesh in &self.meshes {
+                if cancelled() {
+                    return false;
+                }
+                draw_bounding_box(context, mesh, rot);
+            }
+        } else if let Some(style) = self.points {
+            for mesh in &self.meshes {
+                if cancelled() {
+                    return false;
+                }
+                draw_points(context, mesh, rot, shader, &self.lights, style);
+            }
+            keep_solid(context);
+        } else {
+            for mesh in self.meshes.iter().filter(|mesh| !mesh.wireframe) {
+                if !draw_mesh_cancellable(context, mesh, rot, shader, &self.lights, cancel) {
+                    return false;
+                }
+            }
+            keep_solid(context);
+            if let Some(crease_angle) = self.feature_edges {
+                // The meshes drawn above are only kept as depth, to hide the edges behind them
+                context.clear_frame_buffer();
+                for mesh in self.meshes.iter().filter(|mesh| !mesh.wireframe) {
+                    if cancelled() {
+                        return false;
+                    }
+                    draw_feature_edges(context, mesh, rot, crease_angle);
+                }
+            }
+            for mesh in self.meshes.iter().filter(|mesh| mesh.wireframe) {
+                if cancelled() {
+                    return false;
+                }
+                draw_wireframe(context, mesh, rot);
+            }
+        }
+
+        context.flip(self.flips.0, self.flips.1);
+        if let Some(speed) = self.rainbow {
+            context.rotate_hues(speed * self.time);
+        }
+        if self.background.adapt {
+            context.adapt_to_background(self.background.color);
+        }
+        true
+    }
+    // Renders an image context at a fraction of its size first, doubling it every pass until the
+    // last one draws it at full size, preview is handed every pass's frame as soon as it's drawn.
+    // Returns false if cancel was set before the last pass finished
+    pub fn render_progressive<F>(
+        &self,
+        context: &mut Context,
+        passes: u32,
+        cancel: &AtomicBool,
+        mut preview: F,
+    ) -> Result<bool, Box<dyn Error>>
+    where
+        F: FnMut(&Context) -> Result<(), Box<dyn Error>>,
+    {
+        let (width, height) = (context.width, context.height);
+        for pass in (0..passes).rev() {
+            context.width = (width >> pass).max(4); // Two cells per pixel, after the two left empty
+            context.height = (height >> pass).max(2);
+            context.update((0, 0), self)?; // Fits the scene to this pass's size
+            if !self.render_cancellable(context, cancel) {
+                return Ok(false);
+            }
+            preview(context)?;
+        }
+        Ok(true)
+    }
+}
+
+// Copies the frame drawn so far into the solid layer, when it's exported
+fn keep_solid(context: &mut Context) {
+    if context.solid_buffer.is_some() {
+        context.solid_buffer = Some(context.frame_buffer.clone());
+    }
+}
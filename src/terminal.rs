@@ -0,0 +1,123 @@
+use crossterm::{cursor, style::Print, terminal, tty::IsTty, ExecutableCommand, QueueableCommand};
+use std::error::Error;
+use std::io::{stderr, stdout};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// Puts the terminal back how it was found, with the cursor shown and raw mode off
+pub fn restore_terminal() {
+    // Nothing's left to report a failure to while tearing down
+    let _ = stdout().execute(cursor::Show);
+    let _ = terminal::disable_raw_mode();
+}
+
+// Writes a short message over the top left corner of the frame just flushed, until the next one
+pub fn show_notice(text: &str) -> Result<(), Box<dyn Error>> {
+    stdout()
+        .queue(cursor::MoveTo(0, 0))?
+        .queue(Print(format!(" {} ", text)))?;
+    Ok(())
+}
+
+// Sets the flag once SIGINT, SIGTERM or SIGHUP arrives, rather than the process being killed
+// with the terminal still in raw mode
+#[cfg(unix)]
+fn flag_signals(flag: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    for &signal in &[
+        signal_hook::SIGINT,
+        signal_hook::SIGTERM,
+        signal_hook::SIGHUP,
+    ] {
+        signal_hook::flag::register(signal, Arc::clone(flag))?;
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn flag_signals(_flag: &Arc<AtomicBool>) -> Result<(), Box<dyn Error>> {
+    Ok(()) // Ctrl-C arrives as a key in raw mode
+}
+
+// Hides the cursor, and enables raw mode if asked to, for as long as it's alive.
+// The terminal is restored once it's dropped, whether main returns normally,
+// with an error, or a panic unwinds past it. Signals asking the process to stop
+// are caught, the render loop should check signalled and return when it's set
+pub struct TerminalGuard {
+    signalled: Arc<AtomicBool>,
+}
+
+impl TerminalGuard {
+    pub fn new(raw_mode: bool) -> Result<TerminalGuard, Box<dyn Error>> {
+        // The panic message is printed before unwinding reaches the guard, so it's restored first
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore_terminal();
+            default_hook(info);
+        }));
+        let signalled = Arc::new(AtomicBool::new(false));
+        flag_signals(&signalled)?;
+        if raw_mode {
+            terminal::enable_raw_mode()?;
+        }
+        stdout().execute(cursor::Hide)?;
+        Ok(TerminalGuard { signalled })
+    }
+    pub fn signalled(&self) -> bool {
+        self.signalled.load(Ordering::Relaxed)
+    }
+    // The flag signals set, to cancel a frame that's still being drawn when one arrives
+    pub fn signal_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.signalled)
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+const SPINNER: &str = "|/-\\";
+
+// Shows a message after a spinner on stderr for as long as it's alive, so slow
+// loads don't look hung. Nothing is shown unless stderr is a terminal, and the
+// line is cleared again once it's dropped
+pub struct LoadingIndicator {
+    done: Arc<AtomicBool>,
+    spinner: Option<JoinHandle<()>>,
+}
+
+impl LoadingIndicator {
+    pub fn new(message: String) -> LoadingIndicator {
+        let done = Arc::new(AtomicBool::new(false));
+        let spinner = if stderr().is_tty() {
+            let done = Arc::clone(&done);
+            Some(thread::spawn(move || {
+                for frame in SPINNER.chars().cycle() {
+                    if done.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    eprint!("\r{} {}", frame, message);
+                    thread::park_timeout(Duration::from_millis(100)); // Woken early once dropped
+                }
+                eprint!("\r{}\r", " ".repeat(message.chars().count() + 2));
+            }))
+        } else {
+            None
+        };
+        LoadingIndicator { done, spinner }
+    }
+}
+
+impl Drop for LoadingIndicator {
+    fn drop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(spinner) = self.spinner.take() {
+            spinner.thread().unpark();
+            let _ = spinner.join();
+        }
+    }
+}
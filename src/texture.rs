@@ -0,0 +1,163 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// How a texture is sampled between its texels
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextureFilter {
+    Nearest,  // The closest texel, crisp for pixel art
+    Bilinear, // A blend of the four around the sample
+}
+
+// Where a texture is sampled outside 0.0..=1.0
+#[derive(Clone, Copy, PartialEq)]
+pub enum TextureWrap {
+    Repeat, // Tiles the texture
+    Clamp,  // Stretches the edge texels outwards
+    Mirror, // Tiles the texture flipped every other time
+}
+
+impl TextureWrap {
+    // Brings a coordinate into 0.0..=1.0
+    fn wrap(self, t: f32) -> f32 {
+        if !t.is_finite() {
+            return 0.0;
+        }
+        match self {
+            TextureWrap::Repeat => t.rem_euclid(1.0),
+            TextureWrap::Clamp => t.clamp(0.0, 1.0),
+            TextureWrap::Mirror => 1.0 - (t.rem_euclid(2.0) - 1.0).abs(),
+        }
+    }
+}
+
+// An RGB image, row major with the first row at the top
+pub struct Texture {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<(u8, u8, u8)>,
+    pub filter: TextureFilter,
+    pub wrap: TextureWrap,
+}
+
+impl Texture {
+    // Loads a binary (P6) or plain text (P3) PPM image
+    pub fn load(filename: &str) -> Result<Texture, Box<dyn Error>> {
+        let error = |e: &str| -> Result<Texture, Box<dyn Error>> {
+            Err(format!("image: [{}] couldn't load, {}", filename, e).into())
+        };
+        let bytes = match fs::read(Path::new(filename)) {
+            Err(e) => return error(&e.to_string()),
+            Ok(bytes) => bytes,
+        };
+
+        // The header is four whitespace separated tokens, '#' starts a comment until the line ends
+        let mut header: Vec<usize> = vec![];
+        let mut magic = String::new();
+        let mut cursor = 0;
+        while header.len() < 3 || magic.is_empty() {
+            while cursor < bytes.len()
+                && (bytes[cursor].is_ascii_whitespace() || bytes[cursor] == b'#')
+            {
+                if bytes[cursor] == b'#' {
+                    while cursor < bytes.len() && bytes[cursor] != b'\n' {
+                        cursor += 1;
+                    }
+                } else {
+                    cursor += 1;
+                }
+            }
+            let start = cursor;
+            while cursor < bytes.len() && !bytes[cursor].is_ascii_whitespace() {
+                cursor += 1;
+            }
+            if start == cursor {
+                return error("the PPM header is truncated");
+            }
+            let token = String::from_utf8_lossy(&bytes[start..cursor]);
+            if magic.is_empty() {
+                magic = token.to_string();
+            } else {
+                match token.parse() {
+                    Err(_) => return error("the PPM header is malformed"),
+                    Ok(value) => header.push(value),
+                }
+            }
+        }
+        cursor += 1; // Exactly one whitespace byte separates the header from binary data
+
+        let (width, height, max) = (header[0], header[1], header[2]);
+        if max == 0 || max > 65535 {
+            return error("the PPM maximum color value is out of range");
+        }
+        let scale = |value: usize| (value.min(max) * 255 / max) as u8;
+        // Both come straight from the header, so their product may not fit
+        let texels = match width.checked_mul(height) {
+            Some(texels) if texels.checked_mul(3).is_some() => texels,
+            _ => return error("the PPM dimensions are too large"),
+        };
+
+        let samples: Vec<usize> = match &*magic {
+            "P6" if max < 256 => bytes.iter().skip(cursor).map(|&b| usize::from(b)).collect(),
+            "P6" => bytes
+                .get(cursor..)
+                .unwrap_or(&[])
+                .chunks_exact(2)
+                .map(|pair| (usize::from(pair[0]) << 8) | usize::from(pair[1]))
+                .collect(),
+            "P3" => match String::from_utf8_lossy(bytes.get(cursor..).unwrap_or(&[]))
+                .split_whitespace()
+                .map(|token| token.parse())
+                .collect()
+            {
+                Err(_) => return error("the PPM pixel data is malformed"),
+                Ok(samples) => samples,
+            },
+            _ => return error("only P3 and P6 PPM images are supported"),
+        };
+        if samples.len() < texels * 3 {
+            return error("the PPM pixel data is truncated");
+        }
+
+        Ok(Texture {
+            width,
+            height,
+            pixels: samples
+                .chunks_exact(3)
+                .take(texels)
+                .map(|rgb| (scale(rgb[0]), scale(rgb[1]), scale(rgb[2])))
+                .collect(),
+            filter: TextureFilter::Bilinear,
+            wrap: TextureWrap::Repeat,
+        })
+    }
+
+    // Looks up the texture through its wrap mode and filter, v = 0.0 is at the top
+    pub fn sample(&self, u: f32, v: f32) -> (u8, u8, u8) {
+        if self.pixels.is_empty() {
+            return (0, 0, 0);
+        }
+        // Clamped again, so rounding can never index past the last texel
+        let x = (self.wrap.wrap(u) * (self.width - 1) as f32).clamp(0.0, (self.width - 1) as f32);
+        let y = (self.wrap.wrap(v) * (self.height - 1) as f32).clamp(0.0, (self.height - 1) as f32);
+        let texel = |x: usize, y: usize| self.pixels[y * self.width + x];
+        if self.filter == TextureFilter::Nearest {
+            return texel(x.round() as usize, y.round() as usize);
+        }
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x.fract(), y.fract());
+        let lerp = |a: (u8, u8, u8), b: (u8, u8, u8), t: f32| {
+            let mix = |a: u8, b: u8| f32::from(a) + (f32::from(b) - f32::from(a)) * t;
+            (mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2))
+        };
+        let top = lerp(texel(x0, y0), texel(x1, y0), tx);
+        let bottom = lerp(texel(x0, y1), texel(x1, y1), tx);
+        let channel = |a: f32, b: f32| (a + (b - a) * ty).round() as u8;
+        (
+            channel(top.0, bottom.0),
+            channel(top.1, bottom.1),
+            channel(top.2, bottom.2),
+        )
+    }
+}
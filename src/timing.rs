@@ -0,0 +1,46 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+const PACING_WINDOW: usize = 30; // How many recent frames the averages are taken over
+
+// Spaces frames evenly by waiting out the average render time rather than the last one,
+// and smooths the turntable's time step so a single slow frame doesn't make it jump
+pub struct FramePacer {
+    pub frame_time: Duration,
+    renders: VecDeque<Duration>,
+    frames: VecDeque<Duration>,
+}
+
+fn average(durations: &VecDeque<Duration>) -> Duration {
+    if durations.is_empty() {
+        Duration::from_secs(0)
+    } else {
+        durations.iter().sum::<Duration>() / durations.len() as u32
+    }
+}
+
+impl FramePacer {
+    pub fn new(fps: f64) -> FramePacer {
+        FramePacer {
+            frame_time: Duration::from_secs_f64(1.0 / fps),
+            renders: VecDeque::with_capacity(PACING_WINDOW),
+            frames: VecDeque::with_capacity(PACING_WINDOW),
+        }
+    }
+    // How long to wait for input before rendering the next frame
+    pub fn wait(&self) -> Duration {
+        self.frame_time
+            .checked_sub(average(&self.renders))
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+    // Records a finished frame, returns the smoothed time step in seconds
+    pub fn record(&mut self, render: Duration, frame: Duration) -> f32 {
+        if self.frames.len() == PACING_WINDOW {
+            self.renders.pop_front();
+            self.frames.pop_front();
+        }
+        self.renders.push_back(render);
+        self.frames.push_back(frame);
+        average(&self.frames).as_secs_f32()
+    }
+}
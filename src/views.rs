@@ -0,0 +1,53 @@
+use nalgebra::{UnitQuaternion, Vector3};
+use std::error::Error;
+use std::f32::consts::PI;
+
+// The camera orientation of a named view, after the same backwards fix the
+// turntable gets. Every view but iso looks straight down one axis
+pub fn view_preset(name: &str) -> Result<UnitQuaternion<f32>, Box<dyn Error>> {
+    let turn = |angle: f32| UnitQuaternion::from_axis_angle(&Vector3::y_axis(), PI + angle);
+    let tilt = |angle: f32| UnitQuaternion::from_axis_angle(&Vector3::x_axis(), -angle);
+    match name {
+        "front" => Ok(turn(0.0)),
+        "back" => Ok(turn(PI)),
+        "left" => Ok(turn(PI * 0.5)),
+        "right" => Ok(turn(-PI * 0.5)),
+        "top" => Ok(tilt(PI * 0.5) * turn(0.0)),
+        "bottom" => Ok(tilt(-PI * 0.5) * turn(0.0)),
+        // A turn by 45 degrees around the vertical, then a tilt by atan(1/sqrt(2)) towards the camera
+        "iso" => Ok(tilt(0.5f32.sqrt().atan()) * turn(PI * 0.25)),
+        _ => Err(format!(
+            "view: [{}] isn't front, back, left, right, top, bottom or iso",
+            name
+        )
+        .into()),
+    }
+}
+
+// Eases in and out of every view, so the camera comes to rest on each one
+fn smoothstep(t: f32) -> f32 {
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Moves the camera through its views over a number of frames, spending as many frames between
+// each pair of them and taking the shortest turn from one to the next
+pub struct Transition {
+    pub views: Vec<UnitQuaternion<f32>>,
+    pub frames: usize,
+}
+
+impl Transition {
+    pub fn orientation(&self, frame: usize) -> UnitQuaternion<f32> {
+        let segments = self.views.len() - 1;
+        if segments == 0 {
+            return self.views[0];
+        }
+        let progress = frame.min(self.frames) as f32 / (self.frames.max(2) - 1) as f32;
+        let along = progress.min(1.0) * segments as f32;
+        let segment = (along as usize).min(segments - 1);
+        let (from, to) = (self.views[segment], self.views[segment + 1]);
+        // None only when both are the same orientation
+        from.try_slerp(&to, smoothstep(along - segment as f32), 1.0e-6)
+            .unwrap_or(from)
+    }
+}